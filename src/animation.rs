@@ -0,0 +1,201 @@
+use crate::{geometry::Vector, matrix::Matrix, transform::Transform};
+
+/// Transforms pinned at points in time. In between, translation and scale
+/// are interpolated linearly and rotation along the shortest arc.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframes {
+    frames: Vec<(f64, Transform)>,
+}
+
+impl Keyframes {
+    pub fn new(mut frames: Vec<(f64, Transform)>) -> Self {
+        assert!(!frames.is_empty(), "at least one keyframe is needed");
+        frames.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { frames }
+    }
+
+    /// The transform at time `t`, holding the first and last keyframes
+    /// outside their range.
+    pub fn sample(&self, t: f64) -> Transform {
+        let next = self.frames.iter().position(|&(time, _)| time > t);
+        let (t0, a, t1, b) = match next {
+            None => return self.frames[self.frames.len() - 1].1,
+            Some(0) => return self.frames[0].1,
+            Some(i) => {
+                let (t0, a) = self.frames[i - 1];
+                let (t1, b) = self.frames[i];
+                (t0, a, t1, b)
+            }
+        };
+        let f = (t - t0) / (t1 - t0);
+        let (ta, ra, sa) = a.decompose();
+        let (tb, rb, sb) = b.decompose();
+        let Vector(x, y, z) = ta + (tb - ta) * f;
+        let Vector(sx, sy, sz) = sa + (sb - sa) * f;
+        let rotation = Quaternion::from_matrix(ra).slerp(Quaternion::from_matrix(rb), f);
+        Transform::translation(x, y, z)
+            * Transform::from_matrix(rotation.to_matrix())
+            * Transform::scaling(sx, sy, sz)
+    }
+
+    /// `samples` transforms at evenly spaced times while the shutter is
+    /// open, one for each sample of a motion-blurred pixel.
+    pub fn oversample(&self, open: f64, close: f64, samples: usize) -> Vec<Transform> {
+        (0..samples)
+            .map(|i| self.sample(open + (close - open) * (i as f64 + 0.5) / samples as f64))
+            .collect()
+    }
+}
+
+// A unit quaternion w + xi + yj + zk standing for a rotation.
+#[derive(Debug, Clone, Copy)]
+struct Quaternion(f64, f64, f64, f64);
+
+impl Quaternion {
+    fn from_matrix(m: Matrix) -> Self {
+        let r = m.0;
+        let trace = r[0][0] + r[1][1] + r[2][2];
+        // Divide by the largest of the four components to stay accurate.
+        if trace > 0. {
+            let s = 2. * (trace + 1.).sqrt();
+            Quaternion(
+                s / 4.,
+                (r[2][1] - r[1][2]) / s,
+                (r[0][2] - r[2][0]) / s,
+                (r[1][0] - r[0][1]) / s,
+            )
+        } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+            let s = 2. * (1. + r[0][0] - r[1][1] - r[2][2]).sqrt();
+            Quaternion(
+                (r[2][1] - r[1][2]) / s,
+                s / 4.,
+                (r[0][1] + r[1][0]) / s,
+                (r[0][2] + r[2][0]) / s,
+            )
+        } else if r[1][1] > r[2][2] {
+            let s = 2. * (1. + r[1][1] - r[0][0] - r[2][2]).sqrt();
+            Quaternion(
+                (r[0][2] - r[2][0]) / s,
+                (r[0][1] + r[1][0]) / s,
+                s / 4.,
+                (r[1][2] + r[2][1]) / s,
+            )
+        } else {
+            let s = 2. * (1. + r[2][2] - r[0][0] - r[1][1]).sqrt();
+            Quaternion(
+                (r[1][0] - r[0][1]) / s,
+                (r[0][2] + r[2][0]) / s,
+                (r[1][2] + r[2][1]) / s,
+                s / 4.,
+            )
+        }
+    }
+
+    fn to_matrix(self) -> Matrix {
+        let Quaternion(w, x, y, z) = self;
+        Matrix([
+            [
+                1. - 2. * (y * y + z * z),
+                2. * (x * y - z * w),
+                2. * (x * z + y * w),
+                0.,
+            ],
+            [
+                2. * (x * y + z * w),
+                1. - 2. * (x * x + z * z),
+                2. * (y * z - x * w),
+                0.,
+            ],
+            [
+                2. * (x * z - y * w),
+                2. * (y * z + x * w),
+                1. - 2. * (x * x + y * y),
+                0.,
+            ],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    fn dot(self, other: Self) -> f64 {
+        self.0 * other.0 + self.1 * other.1 + self.2 * other.2 + self.3 * other.3
+    }
+
+    fn weighted(self, a: f64, other: Self, b: f64) -> Self {
+        Quaternion(
+            self.0 * a + other.0 * b,
+            self.1 * a + other.1 * b,
+            self.2 * a + other.2 * b,
+            self.3 * a + other.3 * b,
+        )
+    }
+
+    fn slerp(self, other: Self, f: f64) -> Self {
+        // q and -q are the same rotation; pick the one on the shorter arc.
+        let (other, cos) = match self.dot(other) {
+            d if d < 0. => (other.weighted(-1., other, 0.), -d),
+            d => (other, d),
+        };
+        if cos > 0.9995 {
+            let q = self.weighted(1. - f, other, f);
+            let norm = q.dot(q).sqrt();
+            return q.weighted(1. / norm, q, 0.);
+        }
+        let theta = cos.acos();
+        let sin = theta.sin();
+        self.weighted(
+            ((1. - f) * theta).sin() / sin,
+            other,
+            (f * theta).sin() / sin,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::assert_almost_eq;
+    use crate::geometry::Point;
+    use crate::macros::AlmostEq;
+    use crate::transform::Transformable;
+
+    fn keyframes() -> Keyframes {
+        Keyframes::new(vec![
+            (
+                1.,
+                Transform::translation(4., 2., 0.) * Transform::rotation_y(PI / 2.),
+            ),
+            (0., Transform::scaling(2., 2., 2.)),
+        ])
+    }
+
+    #[test]
+    fn sampling_at_a_keyframe_gives_its_transform() {
+        let k = keyframes();
+        assert_almost_eq!(k.sample(0.), Transform::scaling(2., 2., 2.));
+        assert_almost_eq!(
+            k.sample(1.),
+            Transform::translation(4., 2., 0.) * Transform::rotation_y(PI / 2.)
+        );
+        assert_almost_eq!(k.sample(-1.), k.sample(0.));
+        assert_almost_eq!(k.sample(3.), k.sample(1.));
+    }
+
+    #[test]
+    fn sampling_between_keyframes_interpolates() {
+        let k = keyframes();
+        let (translation, _, scale) = k.sample(0.25).decompose();
+        assert_almost_eq!(translation, Vector(1., 0.5, 0.));
+        assert_almost_eq!(scale, Vector(1.75, 1.75, 1.75));
+        let halfway = k.sample(0.5);
+        let expected = Transform::translation(2., 1., 0.)
+            * Transform::rotation_y(PI / 4.)
+            * Transform::scaling(1.5, 1.5, 1.5);
+        assert_almost_eq!(halfway, expected);
+        assert_almost_eq!(
+            Point(0., 0., 0.).transform(k.oversample(0., 1., 2)[1]),
+            Point(3., 1.5, 0.)
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    geometry::Point,
+    ray::Ray,
+    transform::{Transform, Transformable},
+};
+
+/// An axis-aligned box. Unbounded shapes, like planes, use infinite
+/// coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl BoundingBox {
+    pub fn new(min: Point, max: Point) -> Self {
+        Self { min, max }
+    }
+
+    /// A box containing nothing, the identity for `union`.
+    pub fn empty() -> Self {
+        Self {
+            min: Point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn infinite() -> Self {
+        Self {
+            min: Point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            max: Point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.0 > self.max.0 || self.min.1 > self.max.1 || self.min.2 > self.max.2
+    }
+
+    pub fn is_finite(&self) -> bool {
+        [self.min, self.max]
+            .iter()
+            .all(|p| p.0.is_finite() && p.1.is_finite() && p.2.is_finite())
+    }
+
+    pub fn add_point(&mut self, p: Point) -> Self {
+        self.min = Point(
+            self.min.0.min(p.0),
+            self.min.1.min(p.1),
+            self.min.2.min(p.2),
+        );
+        self.max = Point(
+            self.max.0.max(p.0),
+            self.max.1.max(p.1),
+            self.max.2.max(p.2),
+        );
+        *self
+    }
+
+    pub fn union(mut self, other: Self) -> Self {
+        if other.is_empty() {
+            return self;
+        }
+        self.add_point(other.min).add_point(other.max)
+    }
+
+    pub fn center(&self) -> Point {
+        Point(
+            (self.min.0 + self.max.0) / 2.,
+            (self.min.1 + self.max.1) / 2.,
+            (self.min.2 + self.max.2) / 2.,
+        )
+    }
+
+    pub fn corners(&self) -> [Point; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Point(a.0, a.1, a.2),
+            Point(a.0, a.1, b.2),
+            Point(a.0, b.1, a.2),
+            Point(a.0, b.1, b.2),
+            Point(b.0, a.1, a.2),
+            Point(b.0, a.1, b.2),
+            Point(b.0, b.1, a.2),
+            Point(b.0, b.1, b.2),
+        ]
+    }
+
+    /// Slab test: whether the ray passes through the box in front of its
+    /// origin.
+    pub fn intersects(&self, ray: Ray) -> bool {
+        let axis = |origin: f64, direction: f64, min: f64, max: f64| {
+            let t1 = (min - origin) / direction;
+            let t2 = (max - origin) / direction;
+            (t1.min(t2), t1.max(t2))
+        };
+        let (o, d) = (ray.origin, ray.direction);
+        let (xmin, xmax) = axis(o.0, d.0, self.min.0, self.max.0);
+        let (ymin, ymax) = axis(o.1, d.1, self.min.1, self.max.1);
+        let (zmin, zmax) = axis(o.2, d.2, self.min.2, self.max.2);
+        let tmin = xmin.max(ymin).max(zmin);
+        let tmax = xmax.min(ymax).min(zmax);
+        !self.is_empty() && tmin <= tmax && tmax >= 0.
+    }
+}
+
+impl Transformable for BoundingBox {
+    /// The box around the transformed corners. Infinite boxes can't be
+    /// transformed exactly and stay infinite.
+    fn transform(self, t: Transform) -> Self {
+        if self.is_empty() {
+            return self;
+        }
+        if !self.is_finite() {
+            return BoundingBox::infinite();
+        }
+        (self.corners().iter()).fold(BoundingBox::empty(), |mut acc, &c| {
+            acc.add_point(c.transform(t))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vector;
+
+    #[test]
+    fn adding_points_to_an_empty_box() {
+        let mut b = BoundingBox::empty();
+        assert!(b.is_empty());
+        b.add_point(Point(-5., 2., 0.));
+        b.add_point(Point(7., 0., -3.));
+        assert_eq!(b.min, Point(-5., 0., -3.));
+        assert_eq!(b.max, Point(7., 2., 0.));
+        assert!(!b.is_empty());
+    }
+
+    #[test]
+    fn the_union_of_two_boxes() {
+        let a = BoundingBox::new(Point(-5., -2., 0.), Point(7., 4., 4.));
+        let b = BoundingBox::new(Point(8., -7., -2.), Point(14., 2., 8.));
+        let u = a.union(b);
+        assert_eq!(u.min, Point(-5., -7., -2.));
+        assert_eq!(u.max, Point(14., 4., 8.));
+        assert_eq!(a.union(BoundingBox::empty()), a);
+    }
+
+    #[test]
+    fn transforming_a_bounding_box() {
+        let b = BoundingBox::new(Point(-1., -1., -1.), Point(1., 1., 1.));
+        let t = Transform::translation(1., 2., 3.) * Transform::scaling(2., 1., 1.);
+        let tb = b.transform(t);
+        assert_eq!(tb.min, Point(-1., 1., 2.));
+        assert_eq!(tb.max, Point(3., 3., 4.));
+        assert_eq!(
+            BoundingBox::infinite().transform(t),
+            BoundingBox::infinite()
+        );
+    }
+
+    #[test]
+    fn intersecting_a_ray_with_a_bounding_box() {
+        let b = BoundingBox::new(Point(-1., -1., -1.), Point(1., 1., 1.));
+        assert!(b.intersects(Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.))));
+        assert!(b.intersects(Ray::new(Point(0., 0., 0.), Vector(1., 0., 0.))));
+        assert!(!b.intersects(Ray::new(Point(0., 2., -5.), Vector(0., 0., 1.))));
+        assert!(!b.intersects(Ray::new(Point(0., 0., -5.), Vector(0., 0., -1.))));
+        assert!(!b.intersects(Ray::new(Point(-2., 0., 0.), Vector(2., 4., 6.))));
+    }
+}
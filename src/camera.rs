@@ -1,11 +1,46 @@
+use std::{
+    f64::consts::PI,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
 use crate::{
-    canvas::Canvas,
-    geometry::Point,
+    bounds::BoundingBox,
+    canvas::{Canvas, Color},
+    geometry::{Point, Vector},
+    integrator::{Integrator, Whitted},
+    macros::EPSILON,
     ray::Ray,
+    sampling::{halton, jitter},
     transform::{Transform, Transformable},
-    world::World,
+    world::{RayCounter, World},
 };
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderStats {
+    pub elapsed: Duration,
+    pub primary_rays: usize,
+    /// Every ray cast into the world, including reflection, refraction and
+    /// shadow rays.
+    pub total_rays: usize,
+    pub intersections_tested: usize,
+}
+
+/// How pixels are mapped to ray directions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    /// A planar projection onto the z = -1 plane.
+    Perspective,
+    /// An equidistant fisheye: the angle from the view axis grows linearly
+    /// with the distance from the image center, reaching half the field of
+    /// view at the edge of the wider image side.
+    Fisheye,
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub hsize: usize,
     pub vsize: usize,
@@ -14,6 +49,20 @@ pub struct Camera {
     pub pixel_size: f64,
     pub half_width: f64,
     pub half_height: f64,
+    pub exposure: f64,
+    pub vignette: f64,
+    /// Seeds the per-pixel placement of extra samples when supersampling.
+    pub seed: u64,
+    pub projection: Projection,
+    pub integrator: &'static dyn Integrator,
+    /// Radius of the thin lens. 0 is a pinhole camera with everything in
+    /// focus.
+    pub aperture: f64,
+    /// Distance in front of the camera that stays sharp with an aperture.
+    pub focal_distance: f64,
+    /// Relative spread of the focal distance between the red, green and
+    /// blue channels, for chromatic aberration in out-of-focus areas.
+    pub dispersion: f64,
 }
 
 impl Camera {
@@ -48,31 +97,563 @@ impl Camera {
             half_width,
             half_height,
             transform,
+            exposure: 1.,
+            vignette: 0.,
+            seed: 0,
+            projection: Projection::Perspective,
+            integrator: &Whitted,
+            aperture: 0.,
+            focal_distance: 1.,
+            dispersion: 0.,
         }
     }
+
+    pub fn set_seed(&mut self, seed: u64) -> Self {
+        self.seed = seed;
+        *self
+    }
+
+    pub fn set_integrator(&mut self, integrator: &'static dyn Integrator) -> Self {
+        self.integrator = integrator;
+        *self
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        *self
+    }
+
+    /// Blurs what lies away from `focal_distance`, more so with a wider
+    /// `aperture`. Only extra samples go through the lens, so it takes a
+    /// supersampled render to show.
+    pub fn set_depth_of_field(&mut self, aperture: f64, focal_distance: f64) -> Self {
+        self.aperture = aperture.max(0.);
+        self.focal_distance = focal_distance;
+        *self
+    }
+
+    pub fn set_dispersion(&mut self, dispersion: f64) -> Self {
+        self.dispersion = dispersion;
+        *self
+    }
+
+    pub fn set_exposure(&mut self, exposure: f64) -> Self {
+        self.exposure = exposure;
+        *self
+    }
+
+    /// Aims the camera at the center of the world's bounds, looking along
+    /// `direction`, and backs it off until the bounds fit in view.
+    pub fn frame_scene(&mut self, world: &World, direction: Vector) -> Self {
+        let bounds = world.bounds();
+        assert!(
+            bounds.is_finite() && !bounds.is_empty(),
+            "cannot frame a scene with unbounded or no geometry"
+        );
+        let center = bounds.center();
+        let radius = bounds.max.distance(center);
+        let half_view = self.half_width.min(self.half_height).atan();
+        let direction = direction.normalize();
+        let from = center - direction * (radius / half_view.sin());
+        let up = if direction.cross(Vector(0., 1., 0.)).magnitude() < EPSILON {
+            Vector(0., 0., 1.)
+        } else {
+            Vector(0., 1., 0.)
+        };
+        self.transform = Transform::view_transform(from, center, up);
+        *self
+    }
+
+    /// Darkens the image radially: pixels at the corners are scaled by
+    /// `1 - strength`, the center is left untouched.
+    pub fn set_vignette(&mut self, strength: f64) -> Self {
+        self.vignette = strength.clamp(0., 1.);
+        *self
+    }
 }
 
 impl Camera {
+    /// The rays `render_supersampled` would trace through pixel `(x, y)`
+    /// with `samples` samples, for inspecting the sample pattern. The first
+    /// one always goes through the pixel center.
+    pub fn rays_for_pixel(&self, x: usize, y: usize, samples: usize) -> Vec<Ray> {
+        (0..samples).map(|i| self.ray_for_sample(x, y, i)).collect()
+    }
+
+    /// Pixel `(x, y)` on the canvas one unit in front of the camera, in
+    /// world space: its center, and the vectors spanning it towards the
+    /// next pixel to the right and the next one down.
+    pub fn pixel_footprint(&self, x: usize, y: usize) -> (Point, Vector, Vector) {
+        let inverse = self.transform.inverse();
+        let center = Point(
+            self.half_width - (x as f64 + 0.5) * self.pixel_size,
+            self.half_height - (y as f64 + 0.5) * self.pixel_size,
+            -1.,
+        );
+        (
+            center.transform(inverse),
+            Vector(-self.pixel_size, 0., 0.).transform(inverse),
+            Vector(0., -self.pixel_size, 0.).transform(inverse),
+        )
+    }
+
     fn ray_for_pixel(&self, x: usize, y: usize) -> Ray {
-        let xoffset = (x as f64 + 0.5) * self.pixel_size;
-        let yoffset = (y as f64 + 0.5) * self.pixel_size;
+        self.ray_for_sample(x, y, 0)
+    }
+
+    fn ray_for_sample(&self, x: usize, y: usize, sample: usize) -> Ray {
+        self.ray_focused_at(x, y, sample, self.focal_distance)
+    }
+
+    fn ray_focused_at(&self, x: usize, y: usize, sample: usize, focal_distance: f64) -> Ray {
+        let (dx, dy) = self.sample_offset(x, y, sample);
+        let xoffset = (x as f64 + dx) * self.pixel_size;
+        let yoffset = (y as f64 + dy) * self.pixel_size;
         let world_x = self.half_width - xoffset;
         let world_y = self.half_height - yoffset;
-        let pixel = Point(world_x, world_y, -1.).transform(self.transform.inverse());
         let origin = Point(0., 0., 0.).transform(self.transform.inverse());
-        let direction = (pixel - origin).normalize();
-        Ray { origin, direction }
+        let direction = match self.projection {
+            Projection::Perspective => {
+                let pixel = Point(world_x, world_y, -1.).transform(self.transform.inverse());
+                (pixel - origin).normalize()
+            }
+            Projection::Fisheye => {
+                let r = world_x.hypot(world_y);
+                let theta = r / self.half_width.max(self.half_height) * self.field_of_view / 2.;
+                let local = if r == 0. {
+                    Vector(0., 0., -1.)
+                } else {
+                    let s = theta.sin() / r;
+                    Vector(world_x * s, world_y * s, -theta.cos())
+                };
+                local.transform(self.transform.inverse()).normalize()
+            }
+        };
+        let forward = Vector(0., 0., -1.).transform(self.transform.inverse());
+        let cos = direction.dot(forward);
+        if self.aperture == 0. || sample == 0 || cos <= 0. {
+            return Ray { origin, direction };
+        }
+        // Every ray through the lens meets the pinhole ray again on the
+        // focal plane.
+        let focus = origin + direction * (focal_distance / cos);
+        let (lx, ly) = self.lens_offset(x, y, sample);
+        let lens = Point(lx, ly, 0.).transform(self.transform.inverse());
+        Ray {
+            origin: lens,
+            direction: (focus - lens).normalize(),
+        }
+    }
+
+    // Point on the lens disk for the nth sample, spread like
+    // `sample_offset` but with a Halton (5, 7) sequence so it doesn't
+    // correlate with the position within the pixel.
+    fn lens_offset(&self, x: usize, y: usize, sample: usize) -> (f64, f64) {
+        let pixel = (y * self.hsize + x) as u64;
+        let u = (halton(sample, 5) + jitter(!self.seed, 2 * pixel)).fract();
+        let v = (halton(sample, 7) + jitter(!self.seed, 2 * pixel + 1)).fract();
+        let (sin, cos) = (2. * PI * v).sin_cos();
+        let r = self.aperture * u.sqrt();
+        (r * cos, r * sin)
+    }
+
+    // Sub-pixel position of the nth sample: the pixel center first, then a
+    // Halton (2, 3) sequence so any prefix of samples covers the pixel
+    // evenly. The sequence is shifted by an offset hashed from the seed and
+    // the pixel, so neighbouring pixels don't alias in the same way and the
+    // result doesn't depend on the order pixels are rendered in.
+    fn sample_offset(&self, x: usize, y: usize, sample: usize) -> (f64, f64) {
+        if sample == 0 {
+            return (0.5, 0.5);
+        }
+        let pixel = (y * self.hsize + x) as u64;
+        let shift_x = jitter(self.seed, 2 * pixel);
+        let shift_y = jitter(self.seed, 2 * pixel + 1);
+        (
+            (halton(sample, 2) + shift_x).fract(),
+            (halton(sample, 3) + shift_y).fract(),
+        )
+    }
+
+    // Focal distance of the red, green and blue channels. Red focuses a
+    // little further away than green and blue closer when there's
+    // dispersion.
+    fn channel_focus(&self) -> [f64; 3] {
+        let spread = if self.aperture == 0. {
+            0.
+        } else {
+            self.focal_distance * self.dispersion
+        };
+        [
+            self.focal_distance + spread,
+            self.focal_distance,
+            self.focal_distance - spread,
+        ]
+    }
+
+    fn color_for_pixel(&self, world: &World, x: usize, y: usize) -> Color {
+        self.color_for_sample(world, x, y, 0)
+    }
+
+    fn color_for_sample(&self, world: &World, x: usize, y: usize, sample: usize) -> Color {
+        self.color_for_sample_counted(world, x, y, sample, &RayCounter::default())
+    }
+
+    fn color_for_sample_counted(
+        &self,
+        world: &World,
+        x: usize,
+        y: usize,
+        sample: usize,
+        counter: &RayCounter,
+    ) -> Color {
+        let trace = |focal_distance: f64| {
+            let ray = self.ray_focused_at(x, y, sample, focal_distance);
+            counter.count_primary();
+            self.integrator.color_at_counted(world, ray, 5, counter) * self.exposure
+        };
+        let [red, green, blue] = self.channel_focus();
+        let color = if red == blue {
+            trace(green)
+        } else {
+            Color(trace(red).0, trace(green).1, trace(blue).2)
+        };
+        if self.vignette == 0. {
+            return color;
+        }
+        let dx = (x as f64 + 0.5) / self.hsize as f64 - 0.5;
+        let dy = (y as f64 + 0.5) / self.vsize as f64 - 0.5;
+        let r2 = (dx.powi(2) + dy.powi(2)) / 0.5;
+        color * (1. - self.vignette * r2)
     }
 
     pub fn render(&self, world: &World) -> Canvas {
         let mut image = Canvas::new(self.hsize, self.vsize, None);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let color = self.color_for_pixel(world, x, y);
+                image.write_pixel(x, y, color);
+            }
+        }
+        image
+    }
+
+    /// Renders the scene from two eyes `eye_separation` apart, with the
+    /// left eye's view on the left half of a canvas twice as wide.
+    pub fn render_stereo(&self, world: &World, eye_separation: f64) -> Canvas {
+        let mut image = Canvas::new(2 * self.hsize, self.vsize, None);
+        for (i, side) in [-0.5, 0.5].into_iter().enumerate() {
+            // Camera space x points left, and moving the eye one way moves
+            // the scene the other way.
+            let eye = Camera {
+                transform: Transform::translation(side * eye_separation, 0., 0.) * self.transform,
+                ..*self
+            };
+            let view = eye.render(world);
+            for y in 0..self.vsize {
+                for x in 0..self.hsize {
+                    image.write_pixel(i * self.hsize + x, y, view.pixel_at(x, y));
+                }
+            }
+        }
+        image
+    }
+
+    /// Updates `prev`, a render of `old`, into a render of `new`. Only the
+    /// pixels whose rays pass through the bounds of added, removed or
+    /// changed objects are traced again; any change to the lights or the
+    /// world's settings traces everything. Shadows and reflections the
+    /// changed objects cast elsewhere are not tracked.
+    pub fn render_delta(&self, old: &World, new: &World, prev: &Canvas) -> Canvas {
+        assert_eq!((prev.width(), prev.height()), (self.hsize, self.vsize));
+        let diff = old.diff(new);
+        let lights_changed = !(diff.added_lights.is_empty()
+            && diff.removed_lights.is_empty()
+            && diff.changed_lights.is_empty());
+        if lights_changed || !old.same_settings(new) {
+            return self.render(new);
+        }
+        let bounds_of = |world: &World, uuids: &[Uuid]| -> Vec<BoundingBox> {
+            (world.objects.iter())
+                .filter(|o| uuids.contains(&o.uuid))
+                .map(|o| o.bounds())
+                .collect()
+        };
+        let mut dirty = bounds_of(old, &diff.removed_objects);
+        dirty.extend(bounds_of(old, &diff.changed_objects));
+        dirty.extend(bounds_of(new, &diff.changed_objects));
+        dirty.extend(bounds_of(new, &diff.added_objects));
+
+        let mut image = Canvas::new(self.hsize, self.vsize, None);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                // The same rays `color_for_pixel` traces.
+                let touches_dirty = self.channel_focus().iter().any(|&focus| {
+                    let ray = self.ray_focused_at(x, y, 0, focus);
+                    dirty.iter().any(|b| b.intersects(ray))
+                });
+                let color = if touches_dirty {
+                    self.color_for_pixel(new, x, y)
+                } else {
+                    prev.pixel_at(x, y)
+                };
+                image.write_pixel(x, y, color);
+            }
+        }
+        image
+    }
+
+    pub fn render_with_stats(&self, world: &World) -> (Canvas, RenderStats) {
+        let counter = RayCounter::default();
+        let start = Instant::now();
+        let mut image = Canvas::new(self.hsize, self.vsize, None);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let color = self.color_for_sample_counted(world, x, y, 0, &counter);
+                image.write_pixel(x, y, color);
+            }
+        }
+        let stats = RenderStats {
+            elapsed: start.elapsed(),
+            primary_rays: counter.primary_rays(),
+            total_rays: counter.total_rays(),
+            intersections_tested: counter.intersections_tested(),
+        };
+        (image, stats)
+    }
+
+    /// Averages `samples` rays per pixel. The first sample goes through the
+    /// pixel center, so a single sample matches `render`.
+    pub fn render_supersampled(&self, world: &World, samples: usize) -> Canvas {
+        assert!(samples > 0, "at least one sample per pixel is needed");
+        let mut image = Canvas::new(self.hsize, self.vsize, None);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                image.write_pixel(x, y, self.supersample(world, x, y, samples));
+            }
+        }
+        image
+    }
+
+    fn supersample(&self, world: &World, x: usize, y: usize, samples: usize) -> Color {
+        let sum = (0..samples).fold(Color::black(), |acc, i| {
+            acc + self.color_for_sample(world, x, y, i)
+        });
+        sum * (1. / samples as f64)
+    }
+
+    /// Renders in `passes` passes, doubling the number of samples per pixel
+    /// each time, and hands the averaged image to `on_pass` after each one.
+    /// The last image is the same as `render_supersampled` with
+    /// `2^(passes - 1)` samples.
+    pub fn render_progressive(
+        &self,
+        world: &World,
+        passes: usize,
+        mut on_pass: impl FnMut(&Canvas),
+    ) -> Canvas {
+        assert!(passes > 0, "at least one pass is needed");
+        let mut sums = vec![Color::black(); self.hsize * self.vsize];
+        let mut image = Canvas::new(self.hsize, self.vsize, None);
+        let mut samples = 0;
+        for pass in 0..passes {
+            let target = 1 << pass;
+            for y in 0..self.vsize {
+                for x in 0..self.hsize {
+                    let sum = &mut sums[y * self.hsize + x];
+                    for i in samples..target {
+                        *sum = *sum + self.color_for_sample(world, x, y, i);
+                    }
+                    image.write_pixel(x, y, *sum * (1. / target as f64));
+                }
+            }
+            samples = target;
+            on_pass(&image);
+        }
+        image
+    }
+
+    /// Renders row by row, saving the image so far to `path` every
+    /// `flush_every_rows` rows and once more at the end so an external
+    /// viewer can watch it fill in. The extension picks the format, e.g.
+    /// `.png` or `.ppm`.
+    pub fn render_to_file_progressive(
+        &self,
+        world: &World,
+        path: &str,
+        flush_every_rows: usize,
+    ) -> image::ImageResult<Canvas> {
+        self.render_flushing(world, flush_every_rows, |image| image.save(path))
+    }
+
+    /// Renders `frames` frames of the object with id `object` making one
+    /// turn around the vertical axis through its center. Each frame is
+    /// saved to `out_pattern` with `{}` replaced by the zero-padded frame
+    /// number, e.g. `spin-{}.png`.
+    pub fn render_turntable(
+        &self,
+        world: &World,
+        object: Uuid,
+        frames: usize,
+        out_pattern: &str,
+    ) -> image::ImageResult<()> {
+        let mut world = world.clone();
+        let index = (world.objects.iter())
+            .position(|o| o.uuid == object)
+            .expect("the turntable object is not in the world");
+        let start = world.objects[index].transform();
+        let center = world.objects[index].bounds().center();
+        for frame in 0..frames {
+            let angle = 2. * PI * frame as f64 / frames as f64;
+            let spin = Transform::rotation_about(center, Vector(0., 1., 0.), angle);
+            world.objects[index].set_transform(spin * start);
+            let path = out_pattern.replace("{}", &format!("{frame:04}"));
+            self.render(&world).save(&path)?;
+        }
+        Ok(())
+    }
+
+    fn render_flushing(
+        &self,
+        world: &World,
+        flush_every_rows: usize,
+        mut flush: impl FnMut(&Canvas) -> image::ImageResult<()>,
+    ) -> image::ImageResult<Canvas> {
+        assert!(
+            flush_every_rows > 0,
+            "rows between flushes must be positive"
+        );
+        let mut image = Canvas::new(self.hsize, self.vsize, None);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let color = self.color_for_pixel(world, x, y);
+                image.write_pixel(x, y, color);
+            }
+            if (y + 1) % flush_every_rows == 0 || y + 1 == self.vsize {
+                flush(&image)?;
+            }
+        }
+        Ok(image)
+    }
+
+    /// Renders the world along with an alpha mask, stored row by row, that
+    /// is 1.0 where the primary ray hits an object the camera sees and 0.0
+    /// elsewhere.
+    pub fn render_with_alpha(&self, world: &World) -> (Canvas, Vec<f64>) {
+        let mut image = Canvas::new(self.hsize, self.vsize, None);
+        let mut alpha = Vec::with_capacity(self.hsize * self.vsize);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let ray = self.ray_for_pixel(x, y);
+                let hit = world
+                    .first_hit(ray, true, &RayCounter::default())
+                    .1
+                    .is_some();
+                alpha.push(if hit { 1. } else { 0. });
+                image.write_pixel(x, y, self.color_for_pixel(world, x, y));
+            }
+        }
+        (image, alpha)
+    }
+
+    /// White where the object with id `object` is the nearest hit the
+    /// camera sees, black everywhere else, for compositing it separately.
+    pub fn render_object_mask(&self, world: &World, object: Uuid) -> Canvas {
+        let mut mask = Canvas::new(self.hsize, self.vsize, None);
         for y in 0..self.vsize {
             for x in 0..self.hsize {
                 let ray = self.ray_for_pixel(x, y);
-                let color = world.color_at(ray, 5);
+                let (xs, hit) = world.first_hit(ray, true, &RayCounter::default());
+                if hit.is_some_and(|idx| xs[idx].object.uuid == object) {
+                    mask.write_pixel(x, y, Color::white());
+                }
+            }
+        }
+        mask
+    }
+
+    pub fn render_cancellable(&self, world: &World, cancel: &AtomicBool) -> Option<Canvas> {
+        let mut image = Canvas::new(self.hsize, self.vsize, None);
+        for y in 0..self.vsize {
+            if cancel.load(Ordering::Relaxed) {
+                return None;
+            }
+            for x in 0..self.hsize {
+                let color = self.color_for_pixel(world, x, y);
                 image.write_pixel(x, y, color);
             }
         }
+        Some(image)
+    }
+
+    pub fn render_tiled(&self, world: &World, tile_size: usize) -> Canvas {
+        let workers = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        self.render_tiles(tile_size, workers, |x, y| self.color_for_pixel(world, x, y))
+    }
+
+    /// Same as `render_supersampled`, spread over `threads` threads. The
+    /// image doesn't depend on the number of threads.
+    pub fn render_supersampled_parallel(
+        &self,
+        world: &World,
+        samples: usize,
+        threads: usize,
+    ) -> Canvas {
+        assert!(samples > 0, "at least one sample per pixel is needed");
+        self.render_tiles(16, threads.max(1), |x, y| {
+            self.supersample(world, x, y, samples)
+        })
+    }
+
+    // Renders square tiles pulled from a shared queue by `workers` threads.
+    fn render_tiles(
+        &self,
+        tile_size: usize,
+        workers: usize,
+        color: impl Fn(usize, usize) -> Color + Sync,
+    ) -> Canvas {
+        assert!(tile_size > 0, "tile size must be positive");
+        let mut tiles = Vec::new();
+        for y0 in (0..self.vsize).step_by(tile_size) {
+            for x0 in (0..self.hsize).step_by(tile_size) {
+                let x1 = (x0 + tile_size).min(self.hsize);
+                let y1 = (y0 + tile_size).min(self.vsize);
+                tiles.push((x0, y0, x1, y1));
+            }
+        }
+        let next_tile = AtomicUsize::new(0);
+        let workers = workers.min(tiles.len().max(1));
+
+        let rendered: Vec<Vec<(usize, usize, Color)>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut pixels = Vec::new();
+                        loop {
+                            let idx = next_tile.fetch_add(1, Ordering::Relaxed);
+                            let Some(&(x0, y0, x1, y1)) = tiles.get(idx) else {
+                                break;
+                            };
+                            for y in y0..y1 {
+                                for x in x0..x1 {
+                                    pixels.push((x, y, color(x, y)));
+                                }
+                            }
+                        }
+                        pixels
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut image = Canvas::new(self.hsize, self.vsize, None);
+        rendered
+            .into_iter()
+            .flatten()
+            .for_each(|(x, y, color)| image.write_pixel(x, y, color));
         image
     }
 }
@@ -82,9 +663,12 @@ mod tests {
     use crate::{
         canvas::Color,
         geometry::{Point, Vector},
+        light::PointLight,
         macros::AlmostEq,
+        object::Object,
+        shape::{Plane, Primitive},
     };
-    use std::f64::consts::PI;
+    use std::{f64::consts::PI, sync::Arc};
 
     use crate::assert_almost_eq;
 
@@ -102,6 +686,28 @@ mod tests {
         assert_almost_eq!(c.pixel_size, 0.01);
     }
 
+    #[test]
+    fn the_pixel_size_for_a_square_canvas() {
+        for size in [500, 600] {
+            let c = Camera::new(size, size, PI / 3., None);
+            let half_view = (PI / 6.).tan();
+            assert_almost_eq!(c.half_width, half_view);
+            assert_almost_eq!(c.half_height, half_view);
+            assert_almost_eq!(c.pixel_size, 2. * half_view / size as f64);
+        }
+    }
+
+    #[test]
+    fn swapping_the_canvas_sides_swaps_the_half_extents() {
+        let wide = Camera::new(300, 150, PI / 2., None);
+        let tall = Camera::new(150, 300, PI / 2., None);
+        assert_almost_eq!(wide.half_width, 1.);
+        assert_almost_eq!(wide.half_height, 0.5);
+        assert_almost_eq!(tall.half_width, wide.half_height);
+        assert_almost_eq!(tall.half_height, wide.half_width);
+        assert_almost_eq!(tall.pixel_size, wide.pixel_size);
+    }
+
     #[test]
     fn constructiong_a_ray_through_the_center_of_the_canvas() {
         let c = Camera::new(201, 101, PI / 2., None);
@@ -118,6 +724,43 @@ mod tests {
         assert_almost_eq!(r.direction, Vector(0.66519, 0.33259, -0.66851), 1e-5);
     }
 
+    #[test]
+    fn sample_rays_stay_within_their_pixel() {
+        let c = Camera::new(201, 101, PI / 2., None).set_seed(7);
+        assert_eq!(c.rays_for_pixel(30, 70, 1), vec![c.ray_for_pixel(30, 70)]);
+        let rays = c.rays_for_pixel(30, 70, 4);
+        assert_eq!(rays.len(), 4);
+        assert_eq!(rays, c.rays_for_pixel(30, 70, 4));
+        for r in rays {
+            // Where the ray crosses the canvas, one unit in front.
+            let Vector(x, y, z) = r.direction;
+            let (u, v) = (x / -z, y / -z);
+            assert!(u <= c.half_width - 30. * c.pixel_size);
+            assert!(u >= c.half_width - 31. * c.pixel_size);
+            assert!(v <= c.half_height - 70. * c.pixel_size);
+            assert!(v >= c.half_height - 71. * c.pixel_size);
+        }
+    }
+
+    #[test]
+    fn pixel_footprints_tile_the_canvas() {
+        let t = Transform::rotation_y(PI / 4.) * Transform::translation(0., -2., 5.);
+        let c = Camera::new(201, 101, PI / 2., Some(t));
+        let (center, across, down) = c.pixel_footprint(30, 70);
+        let (right, _, _) = c.pixel_footprint(31, 70);
+        let (below, _, _) = c.pixel_footprint(30, 71);
+        assert_almost_eq!(center + across, right);
+        assert_almost_eq!(center + down, below);
+        assert_almost_eq!(across.magnitude(), c.pixel_size);
+        assert_almost_eq!(down.magnitude(), c.pixel_size);
+        let ray = c.ray_for_pixel(30, 70);
+        assert_almost_eq!(ray.direction, (center - ray.origin).normalize());
+
+        let fine = Camera::new(402, 202, PI / 2., Some(t));
+        let (_, fine_across, _) = fine.pixel_footprint(60, 140);
+        assert_almost_eq!(fine_across * (c.pixel_size / fine.pixel_size), across);
+    }
+
     #[test]
     fn constructiong_a_ray_when_the_camera_is_transformed() {
         let c = Camera::new(
@@ -131,6 +774,33 @@ mod tests {
         assert_almost_eq!(r.direction, Vector(2f64.sqrt() / 2., 0., -2f64.sqrt() / 2.));
     }
 
+    #[test]
+    fn fisheye_rays_bend_by_the_field_of_view() {
+        let c = Camera::new(201, 101, PI, None).set_projection(Projection::Fisheye);
+        let center = c.ray_for_pixel(100, 50);
+        assert_almost_eq!(center.direction, Vector(0., 0., -1.));
+
+        // The left edge of the middle row is half a pixel in from the edge,
+        // so slightly less than the 90 degrees of a 180 degree fisheye.
+        let edge = c.ray_for_pixel(0, 50);
+        let theta = PI / 2. * 100. / 100.5;
+        assert_almost_eq!(edge.direction, Vector(theta.sin(), 0., -theta.cos()));
+
+        let flat = Camera::new(201, 101, PI / 2., None);
+        let fisheye = Camera {
+            projection: Projection::Fisheye,
+            ..flat
+        };
+        assert_almost_eq!(
+            fisheye.ray_for_pixel(100, 50).direction,
+            flat.ray_for_pixel(100, 50).direction
+        );
+        assert!(!fisheye
+            .ray_for_pixel(0, 0)
+            .direction
+            .almost_eq(flat.ray_for_pixel(0, 0).direction, EPSILON));
+    }
+
     #[test]
     fn rendering_a_world_with_a_camera() {
         let w = World::default();
@@ -142,4 +812,490 @@ mod tests {
         let image = c.render(&w);
         assert_almost_eq!(image.pixel_at(5, 5), Color(0.38066, 0.47583, 0.2855));
     }
+
+    #[test]
+    fn exposure_scales_rendered_colors() {
+        let mut w = World::empty();
+        w.unlit_fallback = true;
+        w.add_object(Object::sphere().set_color(Color(0.5, 0.5, 0.5)));
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let mut c = Camera::new(11, 11, PI / 2., Some(t));
+        assert_eq!(c.exposure, 1.);
+        assert_eq!(c.render(&w).pixel_at(5, 5), Color(0.5, 0.5, 0.5));
+        let image = c.set_exposure(2.).render(&w);
+        assert_eq!(image.pixel_at(5, 5), Color(1., 1., 1.));
+    }
+
+    #[test]
+    fn a_vignette_darkens_the_corners() {
+        let mut w = World::empty();
+        w.unlit_fallback = true;
+        w.add_object(Object::plane().set_transform(Transform::rotation_x(PI / 2.)));
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let mut c = Camera::new(11, 11, PI / 2., Some(t));
+        let plain = c.render(&w);
+        assert_eq!(plain.pixel_at(0, 0), plain.pixel_at(5, 5));
+        let image = c.set_vignette(0.5).render(&w);
+        assert_eq!(image.pixel_at(5, 5), plain.pixel_at(5, 5));
+        assert!(image.pixel_at(0, 0).0 < image.pixel_at(5, 5).0);
+    }
+
+    #[test]
+    fn rendering_with_an_alpha_channel() {
+        let mut w = World::empty();
+        w.add_object(Object::sphere());
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(11, 11, PI / 2., Some(t));
+        let (image, alpha) = c.render_with_alpha(&w);
+        assert_eq!(alpha.len(), 121);
+        assert_eq!(alpha[5 * 11 + 5], 1.);
+        assert_eq!(alpha[0], 0.);
+        assert_eq!(image.pixel_at(5, 5), c.render(&w).pixel_at(5, 5));
+        w.objects[0].set_visible_primary(false);
+        let (image, alpha) = c.render_with_alpha(&w);
+        assert_eq!(alpha[5 * 11 + 5], 0.);
+        assert_eq!(image.pixel_at(5, 5), image.pixel_at(0, 0));
+        w.objects[0].set_visible_primary(true);
+        w.max_distance = 3.;
+        assert_eq!(c.render_with_alpha(&w).1[5 * 11 + 5], 0.);
+    }
+
+    // A plane that raises `cancel` the first time a ray is tested against
+    // it, standing in for a user cancelling mid-render, and counts the rays
+    // tested against it.
+    #[derive(Debug)]
+    struct CancellingPlane {
+        cancel: Arc<AtomicBool>,
+        tested: AtomicUsize,
+    }
+
+    impl CancellingPlane {
+        fn world(cancel: &Arc<AtomicBool>) -> (World, Arc<CancellingPlane>) {
+            let plane = Arc::new(CancellingPlane {
+                cancel: cancel.clone(),
+                tested: AtomicUsize::new(0),
+            });
+            let w = World {
+                objects: vec![Object::custom(plane.clone())],
+                ..World::empty()
+            };
+            (w, plane)
+        }
+    }
+
+    impl Primitive for CancellingPlane {
+        fn local_intersect(&self, ray: Ray) -> Vec<f64> {
+            self.cancel.store(true, Ordering::Relaxed);
+            self.tested.fetch_add(1, Ordering::Relaxed);
+            Plane.local_intersect(ray)
+        }
+
+        fn local_normal_at(&self, point: Point) -> Vector {
+            Plane.local_normal_at(point)
+        }
+    }
+
+    #[test]
+    fn a_cancelled_render_returns_nothing() {
+        let cancel = Arc::new(AtomicBool::new(true));
+        let (w, plane) = CancellingPlane::world(&cancel);
+        let c = Camera::new(20, 10, PI / 2., None);
+        assert!(c.render_cancellable(&w, &cancel).is_none());
+        assert_eq!(plane.tested.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn cancelling_partway_stops_after_the_current_row() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (w, plane) = CancellingPlane::world(&cancel);
+        let c = Camera::new(20, 10, PI / 2., None);
+        assert!(c.render_cancellable(&w, &cancel).is_none());
+        assert_eq!(plane.tested.load(Ordering::Relaxed), c.hsize);
+    }
+
+    #[test]
+    fn an_uncancelled_render_matches_the_serial_render() {
+        let w = World::default();
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(11, 11, PI / 2., Some(t));
+        let cancel = AtomicBool::new(false);
+        let image = c.render_cancellable(&w, &cancel).unwrap();
+        assert_almost_eq!(image.pixel_at(5, 5), Color(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn tiled_rendering_matches_serial_rendering() {
+        let mut w = World::default();
+        w.add_object(
+            Object::plane()
+                .set_reflective(0.5)
+                .set_transform(Transform::translation(0., -1., 0.)),
+        );
+        let t =
+            Transform::view_transform(Point(0., 1., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(24, 18, PI / 2., Some(t));
+        let serial = c.render(&w);
+        for tile_size in [1, 5, 6, 7, 64] {
+            let tiled = c.render_tiled(&w, tile_size);
+            for y in 0..c.vsize {
+                for x in 0..c.hsize {
+                    assert_eq!(tiled.pixel_at(x, y), serial.pixel_at(x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_single_sample_supersample_matches_the_plain_render() {
+        let w = World::default();
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(11, 11, PI / 2., Some(t));
+        let plain = c.render(&w);
+        let supersampled = c.render_supersampled(&w, 1);
+        for y in 0..c.vsize {
+            for x in 0..c.hsize {
+                assert_eq!(supersampled.pixel_at(x, y), plain.pixel_at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn progressive_rendering_converges_to_the_supersampled_render() {
+        let w = World::default();
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(11, 11, PI / 2., Some(t));
+        let mut calls = 0;
+        let image = c.render_progressive(&w, 4, |_| calls += 1);
+        assert_eq!(calls, 4);
+        let direct = c.render_supersampled(&w, 8);
+        for y in 0..c.vsize {
+            for x in 0..c.hsize {
+                assert_eq!(image.pixel_at(x, y), direct.pixel_at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn rendering_to_a_file_flushes_partial_images() {
+        let w = World::default();
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(7, 5, PI / 2., Some(t));
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("progressive-{}.ppm", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut flushes = 0;
+        let partial = c.render_flushing(&w, 2, |image| {
+            image.save(path)?;
+            assert!(image::open(path).is_ok());
+            flushes += 1;
+            Ok(())
+        });
+        assert!(partial.is_ok());
+        assert_eq!(flushes, 3);
+
+        let image = c.render_to_file_progressive(&w, path, 2).unwrap();
+        let expected = dir.join(format!("progressive-{}-final.ppm", std::process::id()));
+        let expected = expected.to_str().unwrap();
+        c.render(&w).save(expected).unwrap();
+        assert_eq!(
+            std::fs::read(path).unwrap(),
+            std::fs::read(expected).unwrap()
+        );
+        assert_eq!(image.pixel_at(3, 2), partial.unwrap().pixel_at(3, 2));
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(expected).unwrap();
+    }
+
+    #[test]
+    fn a_turntable_saves_one_file_per_frame() {
+        let w = World::default();
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(6, 4, PI / 2., Some(t));
+        let dir = std::env::temp_dir();
+        let pattern = dir.join(format!("turntable-{}-{{}}.png", std::process::id()));
+        let pattern = pattern.to_str().unwrap();
+        c.render_turntable(&w, w.objects[0].uuid, 3, pattern)
+            .unwrap();
+
+        let frame = |i: usize| pattern.replace("{}", &format!("{i:04}"));
+        let plain = dir.join(format!("turntable-{}-plain.png", std::process::id()));
+        let plain = plain.to_str().unwrap();
+        c.render(&w).save(plain).unwrap();
+        assert_eq!(
+            std::fs::read(frame(0)).unwrap(),
+            std::fs::read(plain).unwrap()
+        );
+        for i in 0..3 {
+            std::fs::remove_file(frame(i)).unwrap();
+        }
+        assert!(!std::path::Path::new(&frame(3)).exists());
+        std::fs::remove_file(plain).unwrap();
+    }
+
+    // A glowing white sphere on black, well in front of the focal plane.
+    fn defocused_sphere() -> (World, Camera) {
+        let w = World {
+            objects: vec![Object::sphere().set_emission(Color::white())],
+            ..World::empty()
+        };
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(16, 16, PI / 4., Some(t)).set_depth_of_field(1., 12.);
+        (w, c)
+    }
+
+    #[test]
+    fn an_aperture_blurs_out_of_focus_objects() {
+        let (w, c) = defocused_sphere();
+        let pinhole = Camera { aperture: 0., ..c };
+        assert_eq!(c.render(&w).diff(&pinhole.render(&w)).max(), 0.);
+        let sharp = pinhole.render_supersampled(&w, 16);
+        let blurred = c.render_supersampled(&w, 16);
+        assert!(blurred.diff(&sharp).max() > 0.2);
+        let dispersed = Camera {
+            dispersion: 0.,
+            ..c
+        }
+        .render_supersampled(&w, 16);
+        assert_eq!(dispersed.diff(&blurred).max(), 0.);
+    }
+
+    #[test]
+    fn dispersion_fringes_out_of_focus_edges() {
+        let (w, c) = defocused_sphere();
+        let fringe = |image: &Canvas| {
+            let mut most: f64 = 0.;
+            for y in 0..c.vsize {
+                for x in 0..c.hsize {
+                    let Color(r, _, b) = image.pixel_at(x, y);
+                    most = most.max((r - b).abs());
+                }
+            }
+            most
+        };
+        assert_eq!(fringe(&c.render_supersampled(&w, 16)), 0.);
+        let dispersed = Camera {
+            dispersion: 0.5,
+            ..c
+        }
+        .render_supersampled(&w, 16);
+        assert!(fringe(&dispersed) > 0.1);
+    }
+
+    #[test]
+    fn a_delta_render_only_retraces_around_changed_objects() {
+        let mut old = World::empty();
+        old.add_light(PointLight::new(Point(0., 0., -10.), Color::white()));
+        old.add_object(Object::sphere().set_transform(Transform::translation(-2., 0., 0.)));
+        old.add_object(
+            Object::sphere().set_transform(
+                Transform::translation(2., 0., 0.) * Transform::scaling(0.3, 0.3, 0.3),
+            ),
+        );
+        let mut new = World {
+            objects: old.objects.clone(),
+            lights: old.lights.clone(),
+            ..World::empty()
+        };
+        new.objects[1]
+            .set_transform(Transform::translation(2., 0.5, 0.) * Transform::scaling(0.3, 0.3, 0.3));
+        let c = Camera::new(
+            40,
+            20,
+            PI / 3.,
+            Some(Transform::view_transform(
+                Point(0., 0., -8.),
+                Point(0., 0., 0.),
+                Vector(0., 1., 0.),
+            )),
+        );
+        let prev = c.render(&old);
+        let delta = c.render_delta(&old, &new, &prev);
+        delta.assert_similar(&c.render(&new), 0.);
+        let report = prev.diff(&delta);
+        assert!(report.pixels_over(0.) > 0);
+        for y in 0..20 {
+            for x in 0..20 {
+                assert_eq!(delta.pixel_at(x, y), prev.pixel_at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn a_delta_render_retraces_everything_when_settings_change() {
+        let old = World::default();
+        let c = Camera::new(
+            11,
+            11,
+            PI / 2.,
+            Some(Transform::view_transform(
+                Point(0., 0., -5.),
+                Point(0., 0., 0.),
+                Vector(0., 1., 0.),
+            )),
+        );
+        let prev = c.render(&old);
+        // Only a setting differs, so no object or light marks pixels dirty.
+        let new = World {
+            max_distance: 1.,
+            ..old.clone()
+        };
+        let expected = c.render(&new);
+        assert!(expected.diff(&prev).max() > 0.);
+        c.render_delta(&old, &new, &prev)
+            .assert_similar(&expected, 0.);
+    }
+
+    #[test]
+    fn the_camera_renders_with_its_integrator() {
+        let w = World::default();
+        let from = Point(0., 0., -5.);
+        let view = Transform::view_transform(from, Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(11, 11, PI / 2., Some(view));
+        let normals = Camera {
+            integrator: &crate::integrator::Normals,
+            ..c
+        }
+        .render(&w);
+        assert_almost_eq!(normals.pixel_at(5, 5), Color(0.5, 0.5, 0.));
+        assert_almost_eq!(c.render(&w).pixel_at(5, 5), Color(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn rendering_an_object_mask() {
+        let w = World::default();
+        let c = Camera::new(
+            11,
+            11,
+            PI / 2.,
+            Some(Transform::view_transform(
+                Point(0., 0., -5.),
+                Point(0., 0., 0.),
+                Vector(0., 1., 0.),
+            )),
+        );
+        let outer = c.render_object_mask(&w, w.objects[0].uuid);
+        assert_eq!(outer.pixel_at(5, 5), Color::white());
+        assert_eq!(outer.pixel_at(0, 0), Color::black());
+        // The inner sphere is always hidden behind the outer one.
+        let inner = c.render_object_mask(&w, w.objects[1].uuid);
+        assert_eq!(inner.pixel_at(5, 5), Color::black());
+        // Unless the outer one is hidden from the camera.
+        let mut hidden = w.clone();
+        hidden.objects[0].set_visible_primary(false);
+        let inner = c.render_object_mask(&hidden, w.objects[1].uuid);
+        assert_eq!(inner.pixel_at(5, 5), Color::white());
+        let outer = c.render_object_mask(&hidden, w.objects[0].uuid);
+        assert_eq!(outer.pixel_at(5, 5), Color::black());
+        // Nothing is seen beyond the max distance.
+        let near = World {
+            max_distance: 3.,
+            ..w.clone()
+        };
+        let outer = c.render_object_mask(&near, w.objects[0].uuid);
+        assert_eq!(outer.pixel_at(5, 5), Color::black());
+    }
+
+    #[test]
+    fn rendering_with_stats_counts_the_rays() {
+        let w = World::default();
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let mut c = Camera::new(7, 5, PI / 2., Some(t));
+        let (image, stats) = c.render_with_stats(&w);
+        assert_eq!(stats.primary_rays, 35);
+        assert!(stats.total_rays >= stats.primary_rays);
+        // Objects whose bounding sphere a ray misses aren't tested.
+        assert!(stats.intersections_tested > 0);
+        assert!(stats.intersections_tested <= stats.total_rays * w.objects.len());
+        assert_eq!(image.pixel_at(3, 2), c.render(&w).pixel_at(3, 2));
+        // Dispersion traces a camera ray per color channel.
+        let c = c.set_depth_of_field(0.1, 5.).set_dispersion(0.1);
+        assert_eq!(c.render_with_stats(&w).1.primary_rays, 3 * 35);
+    }
+
+    #[test]
+    fn framing_the_default_world_keeps_its_bounds_in_view() {
+        let w = World::default();
+        for direction in [
+            Vector(0., 0., 1.),
+            Vector(1., -1., 0.5),
+            Vector(0., -1., 0.),
+        ] {
+            let c = Camera::new(40, 30, PI / 3., None).frame_scene(&w, direction);
+            let bounds = w.bounds();
+            assert!(bounds.intersects(c.ray_for_pixel(20, 15)));
+            for corner in bounds.corners() {
+                let p = corner.transform(c.transform);
+                assert!(p.2 < 0.);
+                assert!((p.0 / -p.2).abs() <= c.half_width);
+                assert!((p.1 / -p.2).abs() <= c.half_height);
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_supersampling_is_reproducible_across_thread_counts() {
+        let w = World::default();
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(20, 20, PI / 2., Some(t)).set_seed(1234);
+        let serial = c.render_supersampled(&w, 4);
+        for threads in [1, 2, 3, 8] {
+            let parallel = c.render_supersampled_parallel(&w, 4, threads);
+            for y in 0..c.vsize {
+                for x in 0..c.hsize {
+                    assert_eq!(parallel.pixel_at(x, y), serial.pixel_at(x, y));
+                }
+            }
+        }
+        let again = c.render_supersampled(&w, 4);
+        let reseeded = Camera { seed: 99, ..c }.render_supersampled(&w, 4);
+        let mut differs = false;
+        for y in 0..c.vsize {
+            for x in 0..c.hsize {
+                assert_eq!(again.pixel_at(x, y), serial.pixel_at(x, y));
+                differs |= reseeded.pixel_at(x, y) != serial.pixel_at(x, y);
+            }
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn rendering_a_stereo_pair() {
+        let w = World::default();
+        let t =
+            Transform::view_transform(Point(0., 0., -5.), Point(0., 0., 0.), Vector(0., 1., 0.));
+        let c = Camera::new(11, 11, PI / 2., Some(t));
+        let eye = |x: f64| {
+            let from = Point(x, 0., -5.);
+            let t = Transform::view_transform(from, from + Vector(0., 0., 1.), Vector(0., 1., 0.));
+            Camera::new(11, 11, PI / 2., Some(t)).render(&w)
+        };
+        let half = |image: &Canvas, offset: usize| {
+            let mut view = Canvas::new(11, 11, None);
+            for y in 0..11 {
+                for x in 0..11 {
+                    view.write_pixel(x, y, image.pixel_at(offset + x, y));
+                }
+            }
+            view
+        };
+        let stereo = c.render_stereo(&w, 0.5);
+        assert_eq!(stereo.width(), 22);
+        assert!(half(&stereo, 0).diff(&eye(-0.25)).max() < EPSILON);
+        assert!(half(&stereo, 11).diff(&eye(0.25)).max() < EPSILON);
+        assert!(half(&stereo, 0).diff(&half(&stereo, 11)).max() > 0.);
+        let mono = c.render_stereo(&w, 0.);
+        assert_eq!(half(&mono, 0).diff(&half(&mono, 11)).max(), 0.);
+    }
 }
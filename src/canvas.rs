@@ -1,8 +1,14 @@
-use std::ops::{Add, Mul, Sub};
+use std::{
+    fmt,
+    ops::{Add, Mul, Sub},
+};
 
-use image::{Rgb, RgbImage};
+use image::{Rgb, RgbImage, RgbaImage};
 
-use crate::macros::AlmostEq;
+use crate::{
+    geometry::fmt_triple,
+    macros::{AlmostEq, EPSILON},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color(pub f64, pub f64, pub f64);
@@ -59,6 +65,18 @@ fn f64_to_u8(c: f64) -> u8 {
     ((c * 255.).round() as u8).clamp(0, 255)
 }
 
+/// `(r, g, b)`, or with `{:#}` the clamped 8-bit hex form `#rrggbb`.
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            let [r, g, b] = [self.0, self.1, self.2].map(f64_to_u8);
+            write!(f, "#{r:02x}{g:02x}{b:02x}")
+        } else {
+            fmt_triple(f, self.0, self.1, self.2)
+        }
+    }
+}
+
 impl From<Rgb<u8>> for Color {
     fn from(value: Rgb<u8>) -> Self {
         let [r, g, b] = value.0;
@@ -85,6 +103,92 @@ impl Color {
     pub fn black() -> Self {
         Self(0., 0., 0.)
     }
+    pub fn magenta() -> Self {
+        Self(1., 0., 1.)
+    }
+
+    /// Equality within the crate's `EPSILON`, per channel.
+    pub fn approx(self, other: Self) -> bool {
+        self.almost_eq(other, EPSILON)
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.0.is_finite() && self.1.is_finite() && self.2.is_finite()
+    }
+
+    /// Clamps each channel to [0, 1]. Over-bright colors shift hue towards
+    /// their brightest channel; see `desaturate_to_gamut`.
+    pub fn clamp(self) -> Self {
+        Self(
+            self.0.clamp(0., 1.),
+            self.1.clamp(0., 1.),
+            self.2.clamp(0., 1.),
+        )
+    }
+
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.0 + 0.7152 * self.1 + 0.0722 * self.2
+    }
+
+    /// Encodes a linear color with the sRGB transfer curve.
+    pub fn to_srgb(self) -> Self {
+        let encode = |c: f64| {
+            if c <= 0.0031308 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1. / 2.4) - 0.055
+            }
+        };
+        Color(encode(self.0), encode(self.1), encode(self.2))
+    }
+
+    /// Decodes an sRGB-encoded color back to linear values.
+    pub fn to_linear(self) -> Self {
+        let decode = |c: f64| {
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        Color(decode(self.0), decode(self.1), decode(self.2))
+    }
+
+    /// Brings an over-bright color back into [0, 1] by blending it towards
+    /// the grey of the same luminance until its brightest channel is 1, so
+    /// hue and luminance are kept. Colors brighter than white become white.
+    pub fn desaturate_to_gamut(&self) -> Self {
+        let max = self.0.max(self.1).max(self.2);
+        if max <= 1. {
+            return *self;
+        }
+        let l = self.luminance();
+        if l >= 1. {
+            return Color::white();
+        }
+        let grey = Color(l, l, l);
+        grey + (*self - grey) * ((1. - l) / (max - l))
+    }
+
+    pub fn blend(self, other: Self, mode: BlendMode) -> Self {
+        match mode {
+            BlendMode::Normal => other,
+            BlendMode::Multiply => self * other,
+            BlendMode::Screen => {
+                Color::white() - (Color::white() - self) * (Color::white() - other)
+            }
+            BlendMode::Add => self + other,
+        }
+    }
+}
+
+/// How `Color::blend` combines a color with one layered on top of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Add,
 }
 
 pub struct Canvas {
@@ -122,7 +226,26 @@ impl Canvas {
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Non-finite colors are a bug upstream: they panic in debug builds and
+    /// are written as magenta in release builds so they stand out.
     pub fn write_pixel(&mut self, x: usize, y: usize, color: Color) {
+        debug_assert!(
+            color.is_finite(),
+            "non-finite color {color:?} written at ({x}, {y})"
+        );
+        let color = if color.is_finite() {
+            color
+        } else {
+            Color::magenta()
+        };
         let Color(r, g, b) = color;
         self.pixels[(y * self.width + x) * 3..(y * self.width + x + 1) * 3]
             .copy_from_slice(&[r, g, b]);
@@ -134,10 +257,197 @@ impl Canvas {
         Color(r, g, b)
     }
 
+    pub fn flip_vertical(&self) -> Self {
+        let mut flipped = Canvas::new(self.width, self.height, None);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                flipped.write_pixel(x, self.height - 1 - y, self.pixel_at(x, y));
+            }
+        }
+        flipped
+    }
+
+    pub fn flip_horizontal(&self) -> Self {
+        let mut flipped = Canvas::new(self.width, self.height, None);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                flipped.write_pixel(self.width - 1 - x, y, self.pixel_at(x, y));
+            }
+        }
+        flipped
+    }
+
+    /// Rotates the canvas a quarter turn clockwise.
+    pub fn rotate_90(&self) -> Self {
+        let mut rotated = Canvas::new(self.height, self.width, None);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                rotated.write_pixel(self.height - 1 - y, x, self.pixel_at(x, y));
+            }
+        }
+        rotated
+    }
+
+    /// Replaces every pixel with the closest color of `palette`.
+    pub fn quantize(&self, palette: &[Color]) -> Self {
+        self.quantize_with(palette, false)
+    }
+
+    /// Like `quantize`, but with Floyd-Steinberg dithering: each pixel's
+    /// error is pushed onto the neighbours not yet visited, so smooth
+    /// gradients become mixes of palette colors instead of bands.
+    pub fn quantize_dithered(&self, palette: &[Color]) -> Self {
+        self.quantize_with(palette, true)
+    }
+
+    fn quantize_with(&self, palette: &[Color], dither: bool) -> Self {
+        assert!(!palette.is_empty(), "the palette needs at least one color");
+        let distance = |a: Color, b: Color| {
+            let Color(r, g, b) = a - b;
+            r * r + g * g + b * b
+        };
+        let nearest = |c: Color| {
+            (palette.iter().copied())
+                .min_by(|&a, &b| distance(c, a).total_cmp(&distance(c, b)))
+                .unwrap()
+        };
+        let (w, h) = (self.width, self.height);
+        let mut pending: Vec<Color> = (0..w * h).map(|i| self.pixel_at(i % w, i / w)).collect();
+        let mut image = Canvas::new(w, h, None);
+        for y in 0..h {
+            for x in 0..w {
+                let old = pending[y * w + x];
+                let new = nearest(old);
+                image.write_pixel(x, y, new);
+                if !dither {
+                    continue;
+                }
+                let error = old - new;
+                for (dx, dy, weight) in [(1, 0, 7.), (-1, 1, 3.), (0, 1, 5.), (1, 1, 1.)] {
+                    let (nx, ny) = (x as isize + dx, y + dy);
+                    if nx >= 0 && (nx as usize) < w && ny < h {
+                        let i = ny * w + nx as usize;
+                        pending[i] = pending[i] + error * (weight / 16.);
+                    }
+                }
+            }
+        }
+        image
+    }
+
+    /// Bilateral filter: each pixel becomes an average of its neighbours,
+    /// weighted down both by distance and by how different their color is,
+    /// so flat regions are smoothed while edges stay sharp. A sigma that
+    /// isn't positive leaves the image as it is.
+    pub fn denoise(&self, sigma_spatial: f64, sigma_color: f64) -> Self {
+        if !(sigma_spatial > 0. && sigma_color > 0.) {
+            return Canvas {
+                pixels: self.pixels.clone(),
+                ..*self
+            };
+        }
+        let radius = (2. * sigma_spatial).ceil() as usize;
+        let mut out = Canvas::new(self.width, self.height, None);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let center = self.pixel_at(x, y);
+                let (mut sum, mut total) = (Color::black(), 0.);
+                for ny in y.saturating_sub(radius)..(y + radius + 1).min(self.height) {
+                    for nx in x.saturating_sub(radius)..(x + radius + 1).min(self.width) {
+                        let c = self.pixel_at(nx, ny);
+                        let Color(dr, dg, db) = c - center;
+                        let distance2 = (nx.abs_diff(x).pow(2) + ny.abs_diff(y).pow(2)) as f64;
+                        let spatial = distance2 / (2. * sigma_spatial.powi(2));
+                        let range = (dr * dr + dg * dg + db * db) / (2. * sigma_color.powi(2));
+                        let w = (-spatial - range).exp();
+                        sum = sum + c * w;
+                        total += w;
+                    }
+                }
+                out.write_pixel(x, y, sum * (1. / total));
+            }
+        }
+        out
+    }
+
+    /// Compares two canvases of the same size pixel by pixel.
+    pub fn diff(&self, other: &Canvas) -> DiffReport {
+        assert_eq!(
+            (self.width, self.height),
+            (other.width, other.height),
+            "can't diff canvases of different sizes"
+        );
+        let differences = (self.pixels.chunks_exact(3))
+            .zip(other.pixels.chunks_exact(3))
+            .map(|(a, b)| (0..3).map(|i| (a[i] - b[i]).abs()).fold(0., f64::max))
+            .collect();
+        DiffReport { differences }
+    }
+
+    /// Panics if any pixel differs from `other` by more than `tolerance`
+    /// in any channel.
+    pub fn assert_similar(&self, other: &Canvas, tolerance: f64) {
+        let report = self.diff(other);
+        let over = report.pixels_over(tolerance);
+        assert!(
+            over == 0,
+            "{over} pixels differ by more than {tolerance} (max {}, mean {})",
+            report.max(),
+            report.mean()
+        );
+    }
+
     pub fn save(&self, path: &str) -> image::ImageResult<()> {
+        self.to_rgb().save(path)
+    }
+
+    pub fn to_rgb(&self) -> RgbImage {
         let buf: Vec<u8> = self.pixels.iter().map(|pix| f64_to_u8(*pix)).collect();
-        let image = RgbImage::from_vec(self.width as u32, self.height as u32, buf).unwrap();
-        image.save(path)
+        RgbImage::from_vec(self.width as u32, self.height as u32, buf).unwrap()
+    }
+
+    /// Pairs every pixel with its entry in `alpha`, stored row by row like
+    /// the mask from `Camera::render_with_alpha`.
+    pub fn to_rgba(&self, alpha: &[f64]) -> RgbaImage {
+        assert_eq!(
+            alpha.len(),
+            self.width * self.height,
+            "one alpha value per pixel is needed"
+        );
+        let mut buf = Vec::with_capacity(alpha.len() * 4);
+        for (rgb, &a) in self.pixels.chunks_exact(3).zip(alpha) {
+            buf.extend(rgb.iter().map(|c| f64_to_u8(*c)));
+            buf.push(f64_to_u8(a));
+        }
+        RgbaImage::from_vec(self.width as u32, self.height as u32, buf).unwrap()
+    }
+
+    pub fn save_rgba(&self, path: &str, alpha: &[f64]) -> image::ImageResult<()> {
+        self.to_rgba(alpha).save(path)
+    }
+}
+
+/// The largest channel difference of each pixel between two canvases, in
+/// row-major order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffReport {
+    pub differences: Vec<f64>,
+}
+
+impl DiffReport {
+    pub fn max(&self) -> f64 {
+        self.differences.iter().copied().fold(0., f64::max)
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.differences.is_empty() {
+            return 0.;
+        }
+        self.differences.iter().sum::<f64>() / self.differences.len() as f64
+    }
+
+    pub fn pixels_over(&self, threshold: f64) -> usize {
+        self.differences.iter().filter(|&&d| d > threshold).count()
     }
 }
 
@@ -212,9 +522,200 @@ mod tests {
         assert_eq!(c.pixel_at(2, 3), red);
     }
 
+    #[test]
+    fn flipping_a_canvas_vertically() {
+        let mut c = Canvas::new(4, 3, None);
+        c.write_pixel(1, 0, Color::red());
+        let f = c.flip_vertical();
+        assert_eq!(f.pixel_at(1, 2), Color::red());
+        assert_eq!(f.pixel_at(1, 0), Color::black());
+    }
+
+    #[test]
+    fn flipping_a_canvas_horizontally() {
+        let mut c = Canvas::new(4, 3, None);
+        c.write_pixel(1, 0, Color::red());
+        let f = c.flip_horizontal();
+        assert_eq!(f.pixel_at(2, 0), Color::red());
+        assert_eq!(f.pixel_at(1, 0), Color::black());
+    }
+
+    #[test]
+    fn rotating_a_canvas_a_quarter_turn() {
+        let mut c = Canvas::new(4, 3, None);
+        c.write_pixel(1, 0, Color::red());
+        let r = c.rotate_90();
+        assert_eq!(r.width, 3);
+        assert_eq!(r.height, 4);
+        assert_eq!(r.pixel_at(2, 1), Color::red());
+        let back = r.rotate_90().rotate_90().rotate_90();
+        assert_eq!(back.pixel_at(1, 0), Color::red());
+    }
+
     #[test]
     fn saving_a_canvas() {
         let c = Canvas::new(100, 100, Some(Color(1., 0., 0.)));
         c.save("img.png").unwrap();
     }
+
+    #[test]
+    fn displaying_colors() {
+        assert_eq!(Color(1., 0.5, 0.).to_string(), "(1, 0.5, 0)");
+        assert_eq!(format!("{:#}", Color(1., 0.5, 0.)), "#ff8000");
+        assert_eq!(format!("{:#}", Color(2., -1., 0.2)), "#ff0033");
+    }
+
+    #[test]
+    fn saving_a_canvas_with_an_alpha_channel() {
+        let mut c = Canvas::new(3, 2, Some(Color(0.2, 0.4, 0.6)));
+        c.write_pixel(1, 1, Color::red());
+        let opaque = c.to_rgba(&[1.; 6]);
+        let rgb = c.to_rgb();
+        for (rgba, rgb) in opaque.as_raw().chunks(4).zip(rgb.as_raw().chunks(3)) {
+            assert_eq!(&rgba[..3], rgb);
+            assert_eq!(rgba[3], 255);
+        }
+        let alpha = [1., 1., 1., 0., 0., 1.];
+        let cutout = c.to_rgba(&alpha);
+        let alphas: Vec<u8> = cutout.as_raw().chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alphas, vec![255, 255, 255, 0, 0, 255]);
+        let path = std::env::temp_dir().join(format!("rgba-{}.png", std::process::id()));
+        c.save_rgba(path.to_str().unwrap(), &alpha).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "non-finite color")]
+    fn writing_a_nan_color_panics_in_debug_builds() {
+        let mut c = Canvas::new(2, 2, None);
+        c.write_pixel(1, 0, Color(f64::NAN, 0., 0.));
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn writing_a_nan_color_writes_magenta_in_release_builds() {
+        let mut c = Canvas::new(2, 2, None);
+        c.write_pixel(1, 0, Color(f64::NAN, 0., f64::INFINITY));
+        assert_eq!(c.pixel_at(1, 0), Color::magenta());
+    }
+
+    #[test]
+    fn blending_colors() {
+        let a = Color(0.5, 0.2, 1.);
+        let b = Color(0.5, 0.4, 0.);
+        assert_eq!(a.blend(b, BlendMode::Normal), b);
+        assert_almost_eq!(a.blend(b, BlendMode::Multiply), Color(0.25, 0.08, 0.));
+        assert_almost_eq!(a.blend(b, BlendMode::Screen), Color(0.75, 0.52, 1.));
+        assert_almost_eq!(a.blend(b, BlendMode::Add), Color(1., 0.6, 1.));
+    }
+
+    #[test]
+    fn diffing_canvases() {
+        let a = Canvas::new(4, 3, Some(Color(0.2, 0.4, 0.6)));
+        let mut b = Canvas::new(4, 3, Some(Color(0.2, 0.4, 0.6)));
+        let same = a.diff(&b);
+        assert_eq!(same.max(), 0.);
+        assert_eq!(same.mean(), 0.);
+        assert_eq!(same.pixels_over(0.), 0);
+        a.assert_similar(&b, 0.);
+
+        b.write_pixel(1, 2, Color(0.2, 1., 0.6));
+        let report = a.diff(&b);
+        assert_almost_eq!(report.max(), 0.6);
+        assert_almost_eq!(report.mean(), 0.05);
+        assert_eq!(report.pixels_over(0.01), 1);
+        assert!(report.differences[2 * 4 + 1] > 0.);
+        a.assert_similar(&b, 0.7);
+    }
+
+    #[test]
+    #[should_panic]
+    fn assert_similar_fails_on_a_changed_pixel() {
+        let a = Canvas::new(2, 2, None);
+        let mut b = Canvas::new(2, 2, None);
+        b.write_pixel(0, 0, Color(0.1, 0., 0.));
+        a.assert_similar(&b, 0.01);
+    }
+
+    #[test]
+    fn denoising_with_a_zero_sigma_leaves_the_image_alone() {
+        let mut c = Canvas::new(3, 3, Some(Color(0.2, 0.4, 0.6)));
+        c.write_pixel(1, 1, Color::white());
+        for (spatial, color) in [(0., 0.1), (1., 0.), (-1., 0.1), (f64::NAN, 0.1)] {
+            c.assert_similar(&c.denoise(spatial, color), 0.);
+        }
+    }
+
+    #[test]
+    fn denoising_smooths_noise_but_keeps_edges() {
+        let uniform = Canvas::new(5, 5, Some(Color(0.3, 0.5, 0.7)));
+        uniform.assert_similar(&uniform.denoise(1.5, 0.1), 1e-12);
+
+        // Left half: mid grey with small noise. Right half: white. The
+        // noise is well within sigma_color, the edge well beyond it.
+        let mut noisy = Canvas::new(8, 8, None);
+        for y in 0..8 {
+            for x in 0..8 {
+                let c = if x < 4 {
+                    let n = if (x + y) % 2 == 0 { 0.05 } else { -0.05 };
+                    Color(0.5 + n, 0.5 + n, 0.5 + n)
+                } else {
+                    Color::white()
+                };
+                noisy.write_pixel(x, y, c);
+            }
+        }
+        let smooth = noisy.denoise(1., 0.2);
+        let variance = |c: &Canvas, xs: std::ops::Range<usize>| {
+            let values: Vec<f64> = (0..8)
+                .flat_map(|y| xs.clone().map(move |x| (x, y)))
+                .map(|(x, y)| c.pixel_at(x, y).0)
+                .collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+        };
+        assert!(variance(&smooth, 0..3) < variance(&noisy, 0..3) / 4.);
+        let edge = smooth.pixel_at(4, 4).0 - smooth.pixel_at(3, 4).0;
+        assert!(edge > 0.4);
+    }
+
+    #[test]
+    fn quantizing_to_a_black_and_white_palette() {
+        let palette = [Color::black(), Color::white()];
+        let mut c = Canvas::new(2, 1, None);
+        c.write_pixel(0, 0, Color(0.4, 0.4, 0.4));
+        c.write_pixel(1, 0, Color(0.6, 0.6, 0.6));
+        let q = c.quantize(&palette);
+        assert_eq!(q.pixel_at(0, 0), Color::black());
+        assert_eq!(q.pixel_at(1, 0), Color::white());
+
+        let gray = Canvas::new(4, 2, Some(Color(0.4, 0.4, 0.4)));
+        let flat = gray.quantize(&palette);
+        let dithered = gray.quantize_dithered(&palette);
+        let whites = |c: &Canvas| {
+            (0..8)
+                .filter(|&i| c.pixel_at(i % 4, i / 4) == Color::white())
+                .count()
+        };
+        assert_eq!(whites(&flat), 0);
+        // The first pixel's error pushes its right neighbour over 0.5.
+        assert_eq!(dithered.pixel_at(0, 0), Color::black());
+        assert_eq!(dithered.pixel_at(1, 0), Color::white());
+        assert!(whites(&dithered) > 2 && whites(&dithered) < 6);
+    }
+
+    #[test]
+    fn desaturating_an_over_bright_color_keeps_its_hue() {
+        let c = Color(1.5, 0.5, 0.5);
+        assert_eq!(c.clamp(), Color(1., 0.5, 0.5));
+        let d = c.desaturate_to_gamut();
+        assert_almost_eq!(d.0, 1.);
+        assert_eq!(d.1, d.2);
+        assert!(d.1 > 0.6);
+        assert_almost_eq!(d.luminance(), c.luminance());
+        let in_gamut = Color(0.2, 0.4, 1.);
+        assert_eq!(in_gamut.desaturate_to_gamut(), in_gamut);
+        assert_eq!(Color(3., 2., 2.).desaturate_to_gamut(), Color::white());
+    }
 }
@@ -17,10 +17,7 @@ pub fn floor_with_3_spheres() {
         .set_specular(0.)
         .set_reflective(1.);
 
-    let light_source = PointLight {
-        position: Point(-10., 10., -10.),
-        intensity: Color::white(),
-    };
+    let light_source = PointLight::new(Point(-10., 10., -10.), Color::white());
 
     let middle = Object::sphere()
         .set_transform(Transform::translation(-0.5, 1., 0.5))
@@ -48,6 +45,7 @@ pub fn floor_with_3_spheres() {
     let world = World {
         lights: vec![light_source],
         objects: vec![floor, middle, left, right],
+        ..World::empty()
     };
 
     let camera = Camera::new(
@@ -75,10 +73,7 @@ pub fn floor_with_3_spheres_and_wall() {
         .set_specular(0.)
         .set_transform(Transform::translation(0., 0., 10.) * Transform::rotation_x(PI / 2.));
 
-    let light_source = PointLight {
-        position: Point(-10., 10., -10.),
-        intensity: Color::white(),
-    };
+    let light_source = PointLight::new(Point(-10., 10., -10.), Color::white());
 
     let middle = Object::sphere()
         .set_transform(Transform::translation(-0.5, 1., 0.5))
@@ -103,6 +98,7 @@ pub fn floor_with_3_spheres_and_wall() {
     let world = World {
         lights: vec![light_source],
         objects: vec![floor, wall, middle, left, right],
+        ..World::empty()
     };
 
     let camera = Camera::new(
@@ -125,10 +121,7 @@ pub fn floor_with_pattern() {
         .set_specular(0.)
         .set_pattern(Pattern::stripe_pattern(Color::white(), Color::black()));
 
-    let light_source = PointLight {
-        position: Point(-10., 10., -10.),
-        intensity: Color::white(),
-    };
+    let light_source = PointLight::new(Point(-10., 10., -10.), Color::white());
 
     let middle = Object::sphere()
         .set_transform(Transform::translation(-0.5, 1., 0.5))
@@ -156,6 +149,7 @@ pub fn floor_with_pattern() {
     let world = World {
         lights: vec![light_source],
         objects: vec![floor, middle, left, right],
+        ..World::empty()
     };
 
     let camera = Camera::new(
@@ -178,13 +172,11 @@ pub fn checkered_plane() {
         .set_specular(0.)
         .set_pattern(Pattern::checkers_pattern(Color::white(), Color::black()));
 
-    let light_source = PointLight {
-        position: Point(-10., 10., -10.),
-        intensity: Color::white(),
-    };
+    let light_source = PointLight::new(Point(-10., 10., -10.), Color::white());
     let world = World {
         lights: vec![light_source],
         objects: vec![floor],
+        ..World::empty()
     };
 
     let camera = Camera::new(
@@ -208,13 +200,11 @@ pub fn checkered_sphere() {
         .set_transform(Transform::translation(-0.5, 1., 0.5) * Transform::rotation_y(PI / 4.))
         .set_pattern(pattern);
 
-    let light_source = PointLight {
-        position: Point(-10., 10., -10.),
-        intensity: Color::white(),
-    };
+    let light_source = PointLight::new(Point(-10., 10., -10.), Color::white());
     let world = World {
         lights: vec![light_source],
         objects: vec![sphere],
+        ..World::empty()
     };
 
     let camera = Camera::new(
@@ -237,13 +227,11 @@ pub fn gradient_plane() {
         .set_specular(0.)
         .set_pattern(Pattern::gradient_pattern(Color::white(), Color::black()));
 
-    let light_source = PointLight {
-        position: Point(-10., 10., -10.),
-        intensity: Color::white(),
-    };
+    let light_source = PointLight::new(Point(-10., 10., -10.), Color::white());
     let world = World {
         lights: vec![light_source],
         objects: vec![floor],
+        ..World::empty()
     };
 
     let camera = Camera::new(
@@ -266,13 +254,11 @@ pub fn gradient_sphere() {
         .set_transform(Transform::translation(-0.5, 1., 0.5))
         .set_pattern(pattern);
 
-    let light_source = PointLight {
-        position: Point(-10., 10., -10.),
-        intensity: Color::white(),
-    };
+    let light_source = PointLight::new(Point(-10., 10., -10.), Color::white());
     let world = World {
         lights: vec![light_source],
         objects: vec![sphere],
+        ..World::empty()
     };
 
     let camera = Camera::new(
@@ -294,13 +280,11 @@ pub fn ring_plane() {
         .set_specular(0.)
         .set_pattern(Pattern::ring_pattern(Color::white(), Color::black()));
 
-    let light_source = PointLight {
-        position: Point(-10., 10., -10.),
-        intensity: Color::white(),
-    };
+    let light_source = PointLight::new(Point(-10., 10., -10.), Color::white());
     let world = World {
         lights: vec![light_source],
         objects: vec![floor],
+        ..World::empty()
     };
 
     let camera = Camera::new(
@@ -323,13 +307,11 @@ pub fn ring_sphere() {
         .set_transform(Transform::translation(-0.5, 1., 0.5))
         .set_pattern(pattern);
 
-    let light_source = PointLight {
-        position: Point(-10., 10., -10.),
-        intensity: Color::white(),
-    };
+    let light_source = PointLight::new(Point(-10., 10., -10.), Color::white());
     let world = World {
         lights: vec![light_source],
         objects: vec![sphere],
+        ..World::empty()
     };
 
     let camera = Camera::new(
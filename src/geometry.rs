@@ -1,7 +1,10 @@
-use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::{
+    fmt,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+};
 
 use crate::{
-    macros::AlmostEq,
+    macros::{AlmostEq, EPSILON},
     transform::{Transformable, Transformed},
 };
 
@@ -11,6 +14,29 @@ pub struct Vector(pub f64, pub f64, pub f64);
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Point(pub f64, pub f64, pub f64);
 
+// Writes `(x, y, z)`, passing the formatter's precision on to each part.
+pub(crate) fn fmt_triple(f: &mut fmt::Formatter<'_>, x: f64, y: f64, z: f64) -> fmt::Result {
+    write!(f, "(")?;
+    fmt::Display::fmt(&x, f)?;
+    write!(f, ", ")?;
+    fmt::Display::fmt(&y, f)?;
+    write!(f, ", ")?;
+    fmt::Display::fmt(&z, f)?;
+    write!(f, ")")
+}
+
+impl fmt::Display for Vector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_triple(f, self.0, self.1, self.2)
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_triple(f, self.0, self.1, self.2)
+    }
+}
+
 impl Add for Vector {
     type Output = Self;
 
@@ -27,6 +53,20 @@ impl Add<Vector> for Point {
     }
 }
 
+impl Add<Point> for Vector {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        other + self
+    }
+}
+
+impl AddAssign for Vector {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
 impl Sub for Vector {
     type Output = Self;
 
@@ -35,6 +75,12 @@ impl Sub for Vector {
     }
 }
 
+impl SubAssign for Vector {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
 impl Sub for Point {
     type Output = Vector;
 
@@ -108,6 +154,43 @@ impl Vector {
     pub fn reflect(self, n: Self) -> Self {
         self - n * 2. * self.dot(n)
     }
+
+    /// Equality within the crate's `EPSILON`, per component.
+    pub fn approx(self, other: Self) -> bool {
+        self.almost_eq(other, EPSILON)
+    }
+
+    /// `theta` is the angle down from +y and `phi` the angle around the y
+    /// axis, starting at +z and turning towards +x.
+    pub fn from_spherical(radius: f64, theta: f64, phi: f64) -> Self {
+        Self(
+            radius * theta.sin() * phi.sin(),
+            radius * theta.cos(),
+            radius * theta.sin() * phi.cos(),
+        )
+    }
+}
+
+impl Point {
+    /// The point at `Vector::from_spherical(radius, theta, phi)` from the
+    /// origin.
+    pub fn from_spherical(radius: f64, theta: f64, phi: f64) -> Self {
+        Point(0., 0., 0.) + Vector::from_spherical(radius, theta, phi)
+    }
+
+    /// Equality within the crate's `EPSILON`, per coordinate.
+    pub fn approx(self, other: Self) -> bool {
+        self.almost_eq(other, EPSILON)
+    }
+
+    pub fn distance(self, other: Self) -> f64 {
+        (other - self).magnitude()
+    }
+
+    pub fn distance_squared(self, other: Self) -> f64 {
+        let v = other - self;
+        v.dot(v)
+    }
 }
 
 impl Transformable for Vector {
@@ -228,6 +311,14 @@ mod tests {
         )
     }
     #[test]
+    fn the_distance_between_two_points() {
+        let a = Point(0., 0., 0.);
+        let b = Point(3., 4., 0.);
+        assert_eq!(a.distance(b), 5.);
+        assert_eq!(b.distance(a), 5.);
+        assert_eq!(a.distance_squared(b), 25.);
+    }
+    #[test]
     fn reflecting_a_vector_approaching_at_45_degrees() {
         let v = Vector(1., -1., 0.);
         let n = Vector(0., 1., 0.);
@@ -239,4 +330,57 @@ mod tests {
         let n = Vector(2f64.sqrt() / 2., 2f64.sqrt() / 2., 0.);
         assert_almost_eq!(v.reflect(n), Vector(1., 0., 0.));
     }
+
+    #[test]
+    fn adding_a_point_to_a_vector() {
+        assert_eq!(Vector(1., 0., 0.) + Point(1., 2., 3.), Point(2., 2., 3.));
+        assert_eq!(
+            Vector(3., -2., 5.) + Point(-2., 3., 1.),
+            Point(-2., 3., 1.) + Vector(3., -2., 5.)
+        );
+    }
+
+    #[test]
+    fn accumulating_vectors_in_place() {
+        let mut v = Vector(0., 0., 0.);
+        for _ in 0..3 {
+            v += Vector(1., 2., 3.);
+        }
+        assert_eq!(v, Vector(3., 6., 9.));
+        v -= Vector(1., 1., 1.);
+        assert_eq!(v, Vector(2., 5., 8.));
+    }
+
+    #[test]
+    fn points_from_spherical_coordinates() {
+        use std::f64::consts::{FRAC_PI_2, PI};
+        assert_almost_eq!(Point::from_spherical(1., 0., 0.), Point(0., 1., 0.));
+        assert_almost_eq!(Point::from_spherical(2., PI, 0.), Point(0., -2., 0.));
+        assert_almost_eq!(Point::from_spherical(1., FRAC_PI_2, 0.), Point(0., 0., 1.));
+        assert_almost_eq!(
+            Point::from_spherical(3., FRAC_PI_2, FRAC_PI_2),
+            Point(3., 0., 0.)
+        );
+        let v = Vector::from_spherical(2., PI / 4., PI);
+        assert_almost_eq!(v, Vector(0., 2f64.sqrt(), -(2f64.sqrt())));
+        assert_almost_eq!(v.magnitude(), 2.);
+    }
+
+    #[test]
+    fn approximate_equality_uses_the_crate_epsilon() {
+        assert!(Vector(1.0, 0.0, 0.0).approx(Vector(1.00001, 0.0, 0.0)));
+        assert!(!Vector(1.0, 0.0, 0.0).approx(Vector(1.001, 0.0, 0.0)));
+        assert!(Point(0., 2., 0.).approx(Point(0., 2. + 1e-6, -1e-6)));
+        assert!(!Point(0., 2., 0.).approx(Point(0., 2.1, 0.)));
+    }
+
+    #[test]
+    fn displaying_points_and_vectors() {
+        assert_eq!(Point(1., 2., 3.).to_string(), "(1, 2, 3)");
+        assert_eq!(Vector(0.5, -2., 0.).to_string(), "(0.5, -2, 0)");
+        assert_eq!(
+            format!("{:.2}", Point(1. / 3., 0., 1.)),
+            "(0.33, 0.00, 1.00)"
+        );
+    }
 }
@@ -0,0 +1,140 @@
+use std::fmt::Debug;
+
+use crate::{
+    canvas::Color,
+    ray::Ray,
+    world::{RayCounter, World},
+};
+
+/// Turns a camera ray into a color. The camera uses `Whitted` unless told
+/// otherwise; the others are mostly useful for debugging scenes.
+pub trait Integrator: Debug + Sync {
+    fn color_at(&self, world: &World, ray: Ray, depth: usize) -> Color;
+
+    /// `color_at`, adding the rays it casts to `counter`. Integrators that
+    /// don't override it report nothing.
+    fn color_at_counted(
+        &self,
+        world: &World,
+        ray: Ray,
+        depth: usize,
+        _counter: &RayCounter,
+    ) -> Color {
+        self.color_at(world, ray, depth)
+    }
+}
+
+/// Recursive shading with shadows, reflection and refraction, as done by
+/// `World::color_at`.
+#[derive(Debug, Clone, Copy)]
+pub struct Whitted;
+
+/// The ambient term of the first hit only: no lights, shadows or
+/// secondary rays.
+#[derive(Debug, Clone, Copy)]
+pub struct AmbientOnly;
+
+/// Shows the shading normal of the first hit, mapped from [-1, 1] to
+/// [0, 1] per axis.
+#[derive(Debug, Clone, Copy)]
+pub struct Normals;
+
+impl Integrator for Whitted {
+    fn color_at(&self, world: &World, ray: Ray, depth: usize) -> Color {
+        world.color_at(ray, depth)
+    }
+
+    fn color_at_counted(
+        &self,
+        world: &World,
+        ray: Ray,
+        depth: usize,
+        counter: &RayCounter,
+    ) -> Color {
+        world.color_at_counted(ray, depth, counter)
+    }
+}
+
+impl Integrator for AmbientOnly {
+    fn color_at(&self, world: &World, ray: Ray, depth: usize) -> Color {
+        self.color_at_counted(world, ray, depth, &RayCounter::default())
+    }
+
+    fn color_at_counted(
+        &self,
+        world: &World,
+        ray: Ray,
+        _depth: usize,
+        counter: &RayCounter,
+    ) -> Color {
+        match world.pick_counted(ray, counter) {
+            Some(hit) => {
+                let material = &hit.object.material;
+                material.color_at(hit.object, hit.point) * material.ambient
+            }
+            None => world.background.color_for(ray.direction),
+        }
+    }
+}
+
+impl Integrator for Normals {
+    fn color_at(&self, world: &World, ray: Ray, depth: usize) -> Color {
+        self.color_at_counted(world, ray, depth, &RayCounter::default())
+    }
+
+    fn color_at_counted(
+        &self,
+        world: &World,
+        ray: Ray,
+        _depth: usize,
+        counter: &RayCounter,
+    ) -> Color {
+        match world.pick_counted(ray, counter) {
+            Some(hit) => {
+                let n = hit.normal;
+                Color((n.0 + 1.) / 2., (n.1 + 1.) / 2., (n.2 + 1.) / 2.)
+            }
+            None => Color::black(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assert_almost_eq,
+        geometry::{Point, Vector},
+        macros::AlmostEq,
+    };
+
+    #[test]
+    fn the_whitted_integrator_matches_color_at() {
+        let w = World::default();
+        for r in [
+            Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.)),
+            Ray::new(Point(0., 0., -5.), Vector(0., 1., 0.)),
+            Ray::new(Point(0., 0., 0.75), Vector(0., 0., -1.)),
+        ] {
+            assert_eq!(Whitted.color_at(&w, r, 5), w.color_at(r, 5));
+        }
+    }
+
+    #[test]
+    fn the_normal_integrator_colors_by_direction() {
+        let w = World::default();
+        let front = Normals.color_at(&w, Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.)), 5);
+        assert_almost_eq!(front, Color(0.5, 0.5, 0.));
+        let top = Normals.color_at(&w, Ray::new(Point(0., 5., 0.), Vector(0., -1., 0.)), 5);
+        assert_almost_eq!(top, Color(0.5, 1., 0.5));
+        let miss = Normals.color_at(&w, Ray::new(Point(0., 5., 0.), Vector(0., 1., 0.)), 5);
+        assert_eq!(miss, Color::black());
+    }
+
+    #[test]
+    fn the_ambient_integrator_ignores_lights() {
+        let w = World::default();
+        let c = AmbientOnly.color_at(&w, Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.)), 5);
+        assert_almost_eq!(c, Color(0.08, 0.1, 0.06));
+    }
+}
@@ -2,7 +2,7 @@ use uuid::Uuid;
 
 use crate::{
     geometry::{Point, Vector},
-    macros::EPSILON,
+    macros::{AlmostEq, EPSILON},
     object::Object,
     ray::Ray,
 };
@@ -76,6 +76,18 @@ impl<'inter> Intersection<'inter> {
         hit_index: usize,
         xs: &Intersections,
     ) -> Computations {
+        self.prepare_computations_with_bias(r, hit_index, xs, EPSILON)
+    }
+
+    /// Like `prepare_computations`, but offsets `over_point` and
+    /// `under_point` from the surface by `bias` instead of `EPSILON`.
+    pub fn prepare_computations_with_bias(
+        &self,
+        r: Ray,
+        hit_index: usize,
+        xs: &Intersections,
+        bias: f64,
+    ) -> Computations<'_> {
         let mut n1: f64 = 1.;
         let mut n2: f64 = 1.;
         let mut containers: Vec<(Uuid, f64)> = Vec::with_capacity(hit_index);
@@ -89,6 +101,16 @@ impl<'inter> Intersection<'inter> {
                 }
             }
 
+            // Open surfaces are thin interfaces: a ray refracts once using
+            // their index but never ends up inside them.
+            if !i.object.shape().primitive().is_closed() {
+                if idx == hit_index {
+                    n2 = i.object.material.refractive_index;
+                    break;
+                }
+                continue;
+            }
+
             if let Some(uuid_idx) = containers.iter().position(|&(x, _)| x == i.object.uuid) {
                 containers.remove(uuid_idx);
             } else {
@@ -108,16 +130,28 @@ impl<'inter> Intersection<'inter> {
         let object = self.object;
         let point = r.position(t);
         let eyev = -r.direction;
-        let mut normalv = object.normal_at(point);
+        let mut geometric = object.normal_at(point);
         let inside: bool;
-        if normalv.dot(eyev) < 0. {
+        if geometric.dot(eyev) < 0. {
             inside = true;
-            normalv = -normalv
+            geometric = -geometric
         } else {
             inside = false;
         }
-        let over_point = point + normalv * EPSILON;
-        let under_point = point - normalv * EPSILON;
+        // Offsets follow the true surface; only shading sees the bump map.
+        // Below the surface, stay within half the depth of the next hit so
+        // a shell thinner than `bias` isn't skipped over.
+        let depth = xs.0.get(hit_index + 1).map_or(f64::INFINITY, |next| {
+            (next.t - t) * geometric.dot(r.direction).abs()
+        });
+        let under_bias = if depth > 0. {
+            bias.min(depth / 2.)
+        } else {
+            bias
+        };
+        let over_point = point + geometric * bias;
+        let under_point = point - geometric * under_bias;
+        let normalv = (object.material).bumped_normal(object, point, geometric);
         let reflectv = r.direction.reflect(normalv);
 
         Computations {
@@ -136,6 +170,45 @@ impl<'inter> Intersection<'inter> {
     }
 }
 
+impl Computations<'_> {
+    /// Where a ray heading in `direction` should start: `over_point` when it
+    /// leaves on the eye's side of the surface, `under_point` when it goes
+    /// through.
+    pub fn spawn_point(&self, direction: Vector) -> Point {
+        if (self.over_point - self.point).dot(direction) >= 0. {
+            self.over_point
+        } else {
+            self.under_point
+        }
+    }
+
+    /// The mirror reflection of the eye ray, leaving from just above the
+    /// surface.
+    pub fn reflected_ray(&self) -> Ray {
+        Ray {
+            origin: self.spawn_point(self.reflectv),
+            direction: self.reflectv,
+        }
+    }
+
+    /// The eye ray bent by Snell's law, leaving from just below the
+    /// surface, or `None` under total internal reflection.
+    pub fn refracted_ray(&self) -> Option<Ray> {
+        let n_ratio = self.n1 / self.n2;
+        let cos_i = self.eyev.dot(self.normalv);
+        let sin2_t = n_ratio.powi(2) * (1. - cos_i.powi(2));
+        if sin2_t > 1. {
+            return None;
+        }
+        let cos_t = (1. - sin2_t).sqrt();
+        let direction = self.normalv * (n_ratio * cos_i - cos_t) - self.eyev * n_ratio;
+        Some(Ray {
+            origin: self.spawn_point(direction),
+            direction,
+        })
+    }
+}
+
 impl<'a> Intersections<'a> {
     pub fn push(&mut self, element: Intersection<'a>) {
         let Intersections(v) = self;
@@ -149,6 +222,49 @@ impl<'a> Intersections<'a> {
         v.append(v2);
         v.sort_unstable();
     }
+    /// Combines two sorted lists into one sorted list in linear time.
+    pub fn merge(self, other: Intersections<'a>) -> Self {
+        let (Intersections(a), Intersections(b)) = (self, other);
+        let mut merged = Vec::with_capacity(a.len() + b.len());
+        let (mut a, mut b) = (a.into_iter().peekable(), b.into_iter().peekable());
+        while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+            if y < x {
+                merged.extend(b.next());
+            } else {
+                merged.extend(a.next());
+            }
+        }
+        merged.extend(a);
+        merged.extend(b);
+        Intersections(merged)
+    }
+
+    /// Merges intersections of the same object whose `t` values coincide
+    /// within EPSILON, even when other objects' intersections lie between
+    /// them. Assumes the list is sorted.
+    pub fn dedup(&mut self) {
+        let Intersections(v) = self;
+        let mut kept: Vec<Intersection<'a>> = Vec::with_capacity(v.len());
+        for i in v.drain(..) {
+            // Only the kept entries within EPSILON of `i` can match it.
+            let duplicate = (kept.iter().rev())
+                .take_while(|k| k.t.almost_eq(i.t, EPSILON))
+                .any(|k| k.object.uuid == i.object.uuid);
+            if !duplicate {
+                kept.push(i);
+            }
+        }
+        *v = kept;
+    }
+
+    /// Keeps every intersection behind the ray origin and the nearest `len`
+    /// in front of it, so the hit survives. Assumes the list is sorted.
+    pub fn truncate_ahead(&mut self, len: usize) {
+        let Intersections(v) = self;
+        let behind = v.partition_point(|i| i.t < 0.);
+        v.truncate(behind.saturating_add(len));
+    }
+
     pub fn hit(&self) -> Option<(usize, &Intersection)> {
         self.0.iter().enumerate().find(|(_, i)| i.t >= 0.)
     }
@@ -246,6 +362,52 @@ mod tests {
         );
     }
     #[test]
+    fn merging_sorted_intersection_lists() {
+        let s = Object::sphere();
+        let xs = |ts: &[f64]| {
+            Intersections(ts.iter().map(|&t| Intersection { t, object: &s }).collect())
+        };
+        let ts = |xs: Intersections| xs.0.iter().map(|i| i.t).collect::<Vec<_>>();
+        assert_eq!(ts(xs(&[1., 4.]).merge(xs(&[2., 3.]))), vec![1., 2., 3., 4.]);
+        assert_eq!(ts(xs(&[]).merge(xs(&[2., 3.]))), vec![2., 3.]);
+        assert_eq!(ts(xs(&[1.]).merge(xs(&[]))), vec![1.]);
+        assert!(xs(&[]).merge(xs(&[])).0.is_empty());
+    }
+
+    #[test]
+    fn deduplicating_coincident_intersections_of_the_same_object() {
+        let a = Object::plane();
+        let b = Object::plane();
+        let mut xs = Intersections(vec![
+            Intersection { t: 1., object: &a },
+            Intersection {
+                t: 1. + EPSILON / 10.,
+                object: &a,
+            },
+            Intersection { t: 1., object: &b },
+            Intersection { t: 2., object: &a },
+        ]);
+        xs.dedup();
+        assert_eq!(xs.0.len(), 3);
+        assert_eq!(xs[0].object.uuid, a.uuid);
+        assert_eq!(xs[1].object.uuid, b.uuid);
+        assert_eq!(xs[2].t, 2.);
+
+        // Another object's intersection between the two copies.
+        let mut xs = Intersections(vec![
+            Intersection { t: 1., object: &a },
+            Intersection { t: 1., object: &b },
+            Intersection {
+                t: 1. + EPSILON / 10.,
+                object: &a,
+            },
+        ]);
+        xs.dedup();
+        assert_eq!(xs.0.len(), 2);
+        assert_eq!(xs[0].object.uuid, a.uuid);
+        assert_eq!(xs[1].object.uuid, b.uuid);
+    }
+    #[test]
     fn precomputing_the_state_of_an_intersection() {
         let r = Ray {
             origin: Point(0., 0., -5.),
@@ -369,6 +531,39 @@ mod tests {
         });
     }
     #[test]
+    fn a_transparent_plane_is_a_thin_interface() {
+        let floor = Object::plane()
+            .set_transparency(0.5)
+            .set_refractive_index(1.5);
+        let ball = Object::glass_sphere()
+            .set_transform(Transform::translation(0., -3., 0.))
+            .set_refractive_index(2.);
+        let r = Ray {
+            origin: Point(0., 1., 0.),
+            direction: Vector(0., -1., 0.),
+        };
+        let xs = Intersections(vec![
+            Intersection {
+                t: 1.,
+                object: &floor,
+            },
+            Intersection {
+                t: 3.,
+                object: &ball,
+            },
+            Intersection {
+                t: 5.,
+                object: &ball,
+            },
+        ]);
+        let tests: Vec<(usize, f64, f64)> = vec![(0, 1., 1.5), (1, 1., 2.), (2, 2., 1.)];
+        tests.into_iter().for_each(|(index, n1, n2)| {
+            let comps = xs[index].prepare_computations(r, index, &xs);
+            assert_eq!(comps.n1, n1);
+            assert_eq!(comps.n2, n2);
+        });
+    }
+    #[test]
     fn the_under_point_is_offset_below_the_surface() {
         let r = Ray {
             origin: Point(0., 0., -5.),
@@ -384,4 +579,107 @@ mod tests {
         assert!(comps.under_point.2 > EPSILON / 2.);
         assert!(comps.point.2 < comps.under_point.2);
     }
+
+    #[test]
+    fn a_bump_map_tilts_the_shading_normal() {
+        use std::sync::Arc;
+
+        use crate::{
+            canvas::{Canvas, Color},
+            light::PointLight,
+            pattern::Pattern,
+            texture::{ImageTexture, TextureSampling},
+        };
+        // One period of a sine wave along x, as a height map.
+        let mut heights = Canvas::new(33, 2, None);
+        for x in 0..33 {
+            let h = 0.05 * (0.5 + 0.5 * (x as f64 / 32. * 2. * std::f64::consts::PI).sin());
+            heights.write_pixel(x, 0, Color(h, h, h));
+            heights.write_pixel(x, 1, Color(h, h, h));
+        }
+        let texture = ImageTexture::new(heights).set_sampling(TextureSampling::Bilinear);
+        let bump = Pattern::image(Arc::new(texture));
+        let flat = Object::plane().set_specular(0.);
+        let bumpy = Object::plane().set_specular(0.).set_bump(bump);
+        let light = PointLight::new(Point(0., 10., 0.), Color::white());
+
+        let normal_and_diffuse = |o: &Object, x: f64| {
+            let r = Ray::new(Point(x, 1., 0.5), Vector(0., -1., 0.));
+            let i = Intersection { t: 1., object: o };
+            let comps = i.prepare_computations(r, 0, &Intersections(vec![i]));
+            let c = o
+                .material
+                .lighting(o, &light, comps.point, comps.eyev, comps.normalv, false);
+            (comps.normalv, c.0)
+        };
+        for x in [0.1, 0.4, 0.75] {
+            assert_eq!(normal_and_diffuse(&flat, x).0, Vector(0., 1., 0.));
+        }
+        let (n1, d1) = normal_and_diffuse(&bumpy, 0.1);
+        let (n2, d2) = normal_and_diffuse(&bumpy, 0.25);
+        let (n3, d3) = normal_and_diffuse(&bumpy, 0.4);
+        // Rising slope tilts the normal towards -x, falling towards +x.
+        assert!(n1.0 < -EPSILON && n3.0 > EPSILON);
+        assert!(n2.0.abs() < n1.0.abs());
+        assert!(d2 > d1 && d2 > d3);
+    }
+    #[test]
+    fn the_refracted_ray_under_total_internal_reflection() {
+        let shape = Object::glass_sphere();
+        let r = Ray::new(Point(0., 0., 2f64.sqrt() / 2.), Vector(0., 1., 0.));
+        let xs = Intersections(vec![
+            Intersection {
+                t: -2f64.sqrt() / 2.,
+                object: &shape,
+            },
+            Intersection {
+                t: 2f64.sqrt() / 2.,
+                object: &shape,
+            },
+        ]);
+        let comps = xs[1].prepare_computations(r, 1, &xs);
+        assert_eq!(comps.refracted_ray(), None);
+    }
+    #[test]
+    fn the_refracted_ray_entering_a_glass_sphere() {
+        let shape = Object::glass_sphere();
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        let xs = Intersections(vec![
+            Intersection {
+                t: 4.,
+                object: &shape,
+            },
+            Intersection {
+                t: 6.,
+                object: &shape,
+            },
+        ]);
+        let comps = xs[0].prepare_computations(r, 0, &xs);
+        let refracted = comps.refracted_ray().unwrap();
+        assert_eq!(refracted.origin, comps.under_point);
+        assert!(refracted.origin.2 > -1.);
+        assert!((refracted.direction - Vector(0., 0., 1.)).magnitude() < EPSILON);
+        let reflected = comps.reflected_ray();
+        assert_eq!(reflected.origin, comps.over_point);
+        assert_eq!(reflected.direction, Vector(0., 0., -1.));
+    }
+
+    #[test]
+    fn rays_leaving_a_thin_shell_start_on_the_correct_side() {
+        let outer = Object::glass_sphere();
+        let inner = Object::sphere()
+            .set_transform(Transform::scaling(0.99995, 0.99995, 0.99995))
+            .set_transparency(1.)
+            .set_refractive_index(1.);
+        let r = Ray::new(Point(0., 0.5, -5.), Vector(0., 0., 1.));
+        let mut xs = outer.intersect(r);
+        xs.append(&mut inner.intersect(r));
+        let comps = xs[0].prepare_computations(r, 0, &xs);
+        let refracted = comps.refracted_ray().unwrap();
+        let depth = (refracted.origin - Point(0., 0., 0.)).magnitude();
+        assert!(depth < 1. && depth > 0.99995);
+        let reflected = comps.reflected_ray();
+        let height = (reflected.origin - Point(0., 0., 0.)).magnitude();
+        assert!(height > 1.);
+    }
 }
@@ -1,7 +1,10 @@
+pub mod animation;
+pub mod bounds;
 pub mod camera;
 pub mod canvas;
 pub mod examples;
 pub mod geometry;
+pub mod integrator;
 pub mod intersection;
 pub mod light;
 pub mod macros;
@@ -10,6 +13,8 @@ pub mod matrix;
 pub mod object;
 pub mod pattern;
 pub mod ray;
+pub mod sampling;
 pub mod shape;
+pub mod texture;
 pub mod transform;
 pub mod world;
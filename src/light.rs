@@ -1,7 +1,359 @@
-use crate::{canvas::Color, geometry::Point};
+use std::{f64::consts::PI, sync::Arc};
 
-#[derive(Debug, Clone, Copy)]
+use crate::{
+    canvas::Color,
+    geometry::{Point, Vector},
+    pattern::Pattern,
+    sampling::jitter,
+    texture::ImageTexture,
+    transform::{Transform, Transformable},
+    world::World,
+};
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct PointLight {
     pub position: Point,
     pub intensity: Color,
+    pub gobo: Option<Gobo>,
+    pub spot: Option<Spot>,
+}
+
+/// Limits a light to a cone around `direction`. Points within
+/// `inner_angle` of the axis get the full intensity, points beyond
+/// `outer_angle` get none, and the penumbra in between fades smoothly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spot {
+    pub direction: Vector,
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+}
+
+impl Spot {
+    /// The fraction of the light reaching a point in direction `to_point`.
+    pub fn falloff(&self, to_point: Vector) -> f64 {
+        let cos = to_point.normalize().dot(self.direction.normalize());
+        let (inner, outer) = (self.inner_angle.cos(), self.outer_angle.cos());
+        if cos >= inner {
+            1.
+        } else if cos <= outer {
+            0.
+        } else {
+            let x = (cos - outer) / (inner - outer);
+            x * x * (3. - 2. * x)
+        }
+    }
+}
+
+/// A pattern projected by a light, like a slide in a projector. The light
+/// shines along the -z axis of `transform` (see `Transform::look_at`), and
+/// the pattern is sampled where the light-to-point direction crosses the
+/// plane z = -1 of that frame. Nothing is lit behind the light.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gobo {
+    pub pattern: Pattern,
+    pub transform: Transform,
+}
+
+impl PointLight {
+    pub fn new(position: Point, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+            gobo: None,
+            spot: None,
+        }
+    }
+
+    pub fn set_gobo(&mut self, pattern: Pattern, transform: Transform) -> Self {
+        self.gobo = Some(Gobo { pattern, transform });
+        self.clone()
+    }
+
+    pub fn set_spot(&mut self, direction: Vector, inner_angle: f64, outer_angle: f64) -> Self {
+        self.spot = Some(Spot {
+            direction,
+            inner_angle,
+            outer_angle,
+        });
+        self.clone()
+    }
+
+    /// The light's intensity towards `point`, shaped by its spot cone and
+    /// gobo if any.
+    pub fn intensity_at(&self, point: Point) -> Color {
+        let intensity = match self.spot {
+            Some(spot) => self.intensity * spot.falloff(point - self.position),
+            None => self.intensity,
+        };
+        let Some(gobo) = &self.gobo else {
+            return intensity;
+        };
+        let d = (point - self.position).transform(gobo.transform.inverse());
+        if d.2 >= 0. {
+            return Color::black();
+        }
+        let projected = Point(d.0 / -d.2, d.1 / -d.2, -1.);
+        intensity * gobo.pattern.pattern_at_transformed(projected)
+    }
+}
+
+/// Light arriving from every direction, read from an equirectangular
+/// image: u goes once around the y axis starting behind -z, v from straight
+/// down at 0 to straight up at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentLight {
+    pub texture: Arc<ImageTexture>,
+    pub intensity: f64,
+}
+
+impl EnvironmentLight {
+    pub fn new(texture: Arc<ImageTexture>) -> Self {
+        Self {
+            texture,
+            intensity: 1.,
+        }
+    }
+
+    pub fn set_intensity(&mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self.clone()
+    }
+
+    /// The light arriving from `direction`.
+    pub fn radiance(&self, direction: Vector) -> Color {
+        let d = direction.normalize();
+        let u = 0.5 + d.0.atan2(-d.2) / (2. * PI);
+        let v = 0.5 + d.1.clamp(-1., 1.).asin() / PI;
+        self.texture.uv_pattern_at(u, v) * self.intensity
+    }
+}
+
+/// A rectangular light made of `usteps` x `vsteps` cells. Each cell is
+/// sampled once at a jittered position, so shadows get a soft penumbra
+/// without the banding of a regular grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AreaLight {
+    pub corner: Point,
+    pub uvec: Vector,
+    pub usteps: usize,
+    pub vvec: Vector,
+    pub vsteps: usize,
+    pub intensity: Color,
+    pub position: Point,
+    pub seed: u64,
+}
+
+impl AreaLight {
+    pub fn new(
+        corner: Point,
+        full_uvec: Vector,
+        usteps: usize,
+        full_vvec: Vector,
+        vsteps: usize,
+        intensity: Color,
+    ) -> Self {
+        Self {
+            corner,
+            uvec: full_uvec / usteps as f64,
+            usteps,
+            vvec: full_vvec / vsteps as f64,
+            vsteps,
+            intensity,
+            position: corner + full_uvec / 2. + full_vvec / 2.,
+            seed: 0,
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: u64) -> Self {
+        self.seed = seed;
+        *self
+    }
+
+    pub fn samples(&self) -> usize {
+        self.usteps * self.vsteps
+    }
+
+    pub fn point_on_light(&self, u: usize, v: usize) -> Point {
+        let cell = (v * self.usteps + u) as u64;
+        let ju = jitter(self.seed, 2 * cell);
+        let jv = jitter(self.seed, 2 * cell + 1);
+        self.corner + self.uvec * (u as f64 + ju) + self.vvec * (v as f64 + jv)
+    }
+
+    /// Fraction of the light's samples visible from `point`.
+    pub fn intensity_at(&self, point: Point, world: &World) -> f64 {
+        let mut total = 0.;
+        for v in 0..self.vsteps {
+            for u in 0..self.usteps {
+                if !world.is_shadowed(self.point_on_light(u, v), point) {
+                    total += 1.;
+                }
+            }
+        }
+        total / self.samples() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{object::Object, ray::Ray};
+
+    #[test]
+    fn sampling_an_environment_by_direction() {
+        let mut canvas = crate::canvas::Canvas::new(4, 3, Some(Color::black()));
+        for x in 0..4 {
+            canvas.write_pixel(x, 0, Color::white());
+        }
+        let env = EnvironmentLight::new(Arc::new(ImageTexture::new(canvas))).set_intensity(2.);
+        assert_eq!(env.radiance(Vector(0., 1., 0.)), Color(2., 2., 2.));
+        assert_eq!(env.radiance(Vector(0., -1., 0.)), Color::black());
+        assert_eq!(env.radiance(Vector(1., 0., 0.)), Color::black());
+    }
+
+    fn spotlit_floor(occluded: bool) -> World {
+        let mut w = World::empty();
+        w.add_light(
+            PointLight::new(Point(0., 10., 0.), Color::white()).set_spot(
+                Vector(0., -1., 0.),
+                PI / 12.,
+                PI / 6.,
+            ),
+        );
+        w.add_object(Object::plane());
+        if occluded {
+            w.add_object(Object::sphere().set_transform(Transform::translation(0., 5., 0.)));
+        }
+        w
+    }
+
+    fn floor_color(w: &World, x: f64) -> Color {
+        w.color_at(Ray::new(Point(x, 1., 0.), Vector(0., -1., 0.)), 5)
+    }
+
+    #[test]
+    fn a_spotlight_leaves_surfaces_outside_its_cone_black() {
+        let w = spotlit_floor(false);
+        // 10 units below the light, the cone reaches tan(30°) * 10 ≈ 5.77.
+        assert_eq!(floor_color(&w, 8.), Color::black());
+        assert!(floor_color(&w, 0.).0 > 1.);
+    }
+
+    #[test]
+    fn a_surface_inside_the_cone_is_shadowed_by_an_occluder() {
+        let lit = floor_color(&spotlit_floor(false), 0.);
+        let shadowed = floor_color(&spotlit_floor(true), 0.);
+        assert_eq!(shadowed, Color(0.1, 0.1, 0.1));
+        assert!(lit.0 > shadowed.0);
+    }
+
+    #[test]
+    fn the_penumbra_gets_part_of_the_intensity() {
+        let light = spotlit_floor(false).lights[0].clone();
+        let full = light.intensity_at(Point(1., 0., 0.));
+        let edge = light.intensity_at(Point(4., 0., 0.));
+        assert_eq!(full, Color::white());
+        assert!(edge.0 > 0. && edge.0 < 1.);
+        assert!(light.intensity_at(Point(3., 0., 0.)).0 > edge.0);
+    }
+
+    #[test]
+    fn creating_an_area_light() {
+        let light = AreaLight::new(
+            Point(0., 0., 0.),
+            Vector(2., 0., 0.),
+            4,
+            Vector(0., 0., 1.),
+            2,
+            Color::white(),
+        );
+        assert_eq!(light.uvec, Vector(0.5, 0., 0.));
+        assert_eq!(light.vvec, Vector(0., 0., 0.5));
+        assert_eq!(light.samples(), 8);
+        assert_eq!(light.position, Point(1., 0., 0.5));
+    }
+
+    #[test]
+    fn jittered_samples_stay_within_their_cells() {
+        let light = AreaLight::new(
+            Point(0., 0., 0.),
+            Vector(2., 0., 0.),
+            4,
+            Vector(0., 0., 1.),
+            2,
+            Color::white(),
+        )
+        .set_seed(42);
+        for v in 0..light.vsteps {
+            for u in 0..light.usteps {
+                let p = light.point_on_light(u, v);
+                let (fu, fv) = (p.0 / light.uvec.0, p.2 / light.vvec.2);
+                assert!(fu >= u as f64 && fu < (u + 1) as f64);
+                assert!(fv >= v as f64 && fv < (v + 1) as f64);
+                assert_eq!(p.1, 0.);
+                assert_eq!(p, light.point_on_light(u, v));
+            }
+        }
+    }
+
+    #[test]
+    fn the_seed_changes_the_sample_placement() {
+        let mut light = AreaLight::new(
+            Point(0., 0., 0.),
+            Vector(2., 0., 0.),
+            4,
+            Vector(0., 0., 1.),
+            2,
+            Color::white(),
+        );
+        let a = light.set_seed(1).point_on_light(1, 1);
+        let b = light.set_seed(2).point_on_light(1, 1);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn the_area_light_intensity_function() {
+        let w = World::default();
+        let light = AreaLight::new(
+            Point(-0.5, -0.5, -5.),
+            Vector(1., 0., 0.),
+            2,
+            Vector(0., 1., 0.),
+            2,
+            Color::white(),
+        );
+        assert_eq!(light.intensity_at(Point(0., 0., 2.), &w), 0.);
+        assert_eq!(light.intensity_at(Point(0., 0., -2.), &w), 1.);
+    }
+
+    #[test]
+    fn a_striped_gobo_projects_bands_onto_a_plane() {
+        let from = Point(0., 10., 0.);
+        let aim = Transform::look_at(from, Point(0., 0., 0.), Vector(0., 0., 1.));
+        let stripes = Pattern::stripe_pattern(Color::white(), Color::black())
+            .set_transform(Transform::scaling(0.1, 0.1, 0.1));
+        let light = PointLight::new(from, Color::white()).set_gobo(stripes, aim);
+        let mut w = World::empty();
+        w.add_light(light);
+        w.add_object(Object::plane());
+
+        let colors: Vec<Color> = (-3..3)
+            .map(|i| {
+                let target = Point(i as f64 + 0.5, 0., 0.);
+                let r = Ray::new(Point(target.0, 1., -1.), Vector(0., -1., 1.).normalize());
+                w.color_at(r, 5)
+            })
+            .collect();
+        for pair in colors.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert!(a.0 > 0.5 || b.0 > 0.5);
+            assert!(a.0 < 0.2 || b.0 < 0.2);
+        }
+    }
+
+    #[test]
+    fn a_light_without_a_gobo_is_uniform() {
+        let light = PointLight::new(Point(0., 10., 0.), Color(0.5, 0.5, 0.5));
+        assert_eq!(light.intensity_at(Point(3., 0., -7.)), Color(0.5, 0.5, 0.5));
+    }
 }
@@ -52,10 +52,7 @@ fn main() {
         )),
     );
 
-    let light = PointLight {
-        position: Point(2., 10., -5.),
-        intensity: Color(0.9, 0.9, 0.9),
-    };
+    let light = PointLight::new(Point(2., 10., -5.), Color(0.9, 0.9, 0.9));
     w.add_light(light);
 
     let canvas = camera.render(&w);
@@ -1,20 +1,41 @@
 use crate::canvas::Color;
 use crate::geometry::{Point, Vector};
-use crate::light::PointLight;
+use crate::light::{EnvironmentLight, PointLight};
+use crate::macros::AlmostEq;
 use crate::object::Object;
 use crate::pattern::Pattern;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Material {
     pub color: Color,
     pub ambient: f64,
+    /// Light given off by the surface itself, added on top of any shading.
+    pub emission: Color,
     pub diffuse: f64,
     pub specular: f64,
     pub shininess: f64,
+    /// Use Schlick's approximation of the specular power when `shininess`
+    /// isn't a whole number, trading some accuracy for speed.
+    pub fast_specular: bool,
     pub reflective: f64,
+    /// Grayscale map scaling `reflective` across the surface, e.g. for
+    /// foam on water. White keeps the full reflectivity.
+    pub reflective_pattern: Option<Pattern>,
+    /// Spread of reflected rays, from 0 for a perfect mirror to 1 for a
+    /// reflection blurred over the whole hemisphere.
+    pub roughness: f64,
     pub transparency: f64,
     pub refractive_index: f64,
     pub pattern: Option<Pattern>,
+    /// Height map whose grayscale slope tilts the shading normal, adding
+    /// surface detail without changing the geometry.
+    pub bump: Option<Pattern>,
+    /// Light the back of the surface as if it were the front. One-sided
+    /// surfaces only get ambient light when seen from behind.
+    pub double_sided: bool,
+    /// Shadow opacity of a shadow catcher: the surface shows whatever is
+    /// behind it, darkened by this much where it is in shadow.
+    pub shadow_catcher: Option<f64>,
 }
 
 impl Default for Material {
@@ -22,37 +43,43 @@ impl Default for Material {
         Self {
             color: Color(1., 1., 1.),
             ambient: 0.1,
+            emission: Color::black(),
             diffuse: 0.9,
             specular: 0.9,
             shininess: 200.,
+            fast_specular: false,
             reflective: 0.,
+            reflective_pattern: None,
+            roughness: 0.,
             transparency: 0.,
             refractive_index: 1.,
             pattern: None,
+            bump: None,
+            double_sided: true,
+            shadow_catcher: None,
         }
     }
 }
 
 impl Material {
+    pub fn color_at(&self, object: &Object, point: Point) -> Color {
+        match &self.pattern {
+            Some(p) => p.pattern_at_object(object, point),
+            None => self.color,
+        }
+    }
+
     pub fn lighting(
-        self,
+        &self,
         object: &Object,
-        light: PointLight,
+        light: &PointLight,
         point: Point,
         eyev: Vector,
         normalv: Vector,
         in_shadow: bool,
     ) -> Color {
-        let color: Color;
-        match self.pattern {
-            Some(p) => {
-                color = p.pattern_at_object(object, point);
-            }
-            None => {
-                color = self.color;
-            }
-        }
-        let effective_color = color * light.intensity;
+        let intensity = light.intensity_at(point);
+        let effective_color = self.color_at(object, point) * intensity;
         let lightv = (light.position - point).normalize();
         let ambient = effective_color * self.ambient;
         let light_dot_normal = lightv.dot(normalv);
@@ -68,70 +95,278 @@ impl Material {
             if reflect_dot_eye <= 0. {
                 specular = Color(0., 0., 0.);
             } else {
-                let factor = reflect_dot_eye.powf(self.shininess);
-                specular = light.intensity * self.specular * factor;
+                let factor = self.specular_power(reflect_dot_eye);
+                specular = intensity * self.specular * factor;
             }
         }
         ambient + diffuse + specular
     }
+
+    /// `normal` tilted against the slope of the bump map at `point`, found
+    /// by central differences along two tangents.
+    pub fn bumped_normal(&self, object: &Object, point: Point, normal: Vector) -> Vector {
+        const DELTA: f64 = 1e-3;
+        let Some(bump) = &self.bump else {
+            return normal;
+        };
+        let helper = if normal.0.abs() < 0.9 {
+            Vector(1., 0., 0.)
+        } else {
+            Vector(0., 1., 0.)
+        };
+        let tangent = normal.cross(helper).normalize();
+        let bitangent = normal.cross(tangent);
+        let height = |p: Point| bump.pattern_at_object(object, p).luminance();
+        let slope =
+            |d: Vector| (height(point + d * DELTA) - height(point - d * DELTA)) / (2. * DELTA);
+        (normal - tangent * slope(tangent) - bitangent * slope(bitangent)).normalize()
+    }
+
+    /// Reflectivity at `point`, after the reflectance map if there is one.
+    pub fn reflective_at(&self, object: &Object, point: Point) -> f64 {
+        match &self.reflective_pattern {
+            Some(p) => self.reflective * p.pattern_at_object(object, point).luminance(),
+            None => self.reflective,
+        }
+    }
+
+    /// Ambient light picked up from the environment. A single sample along
+    /// the normal stands in for the whole hemisphere.
+    pub fn environment_lighting(
+        &self,
+        object: &Object,
+        environment: &EnvironmentLight,
+        point: Point,
+        normalv: Vector,
+    ) -> Color {
+        self.color_at(object, point) * environment.radiance(normalv) * self.ambient
+    }
+
+    // `powf` dominates shading cost at high shininess. Whole exponents
+    // go through the much cheaper `powi` instead; otherwise, with
+    // `fast_specular`, Schlick's x / (n - n x + x) stands in for x^n.
+    fn specular_power(&self, x: f64) -> f64 {
+        let n = self.shininess;
+        if n.fract() == 0. && n.abs() <= i32::MAX as f64 {
+            x.powi(n as i32)
+        } else if self.fast_specular {
+            x / (n - n * x + x)
+        } else {
+            x.powf(n)
+        }
+    }
+
+    /// A named starting point for common real-world materials: `gold`,
+    /// `silver`, `plastic_red`, `rubber`, `water` or `glass`.
+    pub fn preset(name: &str) -> Option<Self> {
+        let base = Self::default();
+        let material = match name {
+            "gold" => Self {
+                color: Color(1., 0.766, 0.336),
+                diffuse: 0.3,
+                specular: 1.,
+                shininess: 300.,
+                reflective: 0.6,
+                ..base
+            },
+            "silver" => Self {
+                color: Color(0.972, 0.96, 0.915),
+                diffuse: 0.2,
+                specular: 1.,
+                shininess: 300.,
+                reflective: 0.8,
+                ..base
+            },
+            "plastic_red" => Self {
+                color: Color(0.8, 0.1, 0.1),
+                diffuse: 0.8,
+                specular: 0.5,
+                shininess: 100.,
+                reflective: 0.05,
+                ..base
+            },
+            "rubber" => Self {
+                color: Color(0.1, 0.1, 0.1),
+                diffuse: 0.9,
+                specular: 0.05,
+                shininess: 10.,
+                ..base
+            },
+            "water" => Self {
+                color: Color(0.1, 0.2, 0.3),
+                ambient: 0.,
+                diffuse: 0.1,
+                specular: 1.,
+                shininess: 300.,
+                reflective: 0.3,
+                transparency: 0.9,
+                refractive_index: 1.333,
+                ..base
+            },
+            "glass" => Self {
+                color: Color(0.1, 0.1, 0.1),
+                ambient: 0.,
+                diffuse: 0.1,
+                specular: 1.,
+                shininess: 300.,
+                reflective: 0.9,
+                transparency: 0.9,
+                refractive_index: 1.5,
+                ..base
+            },
+            _ => return None,
+        };
+        Some(material)
+    }
+
+    /// Whether the surface only gives off light, like an area light's
+    /// geometry, so shading it can skip the lights altogether.
+    pub fn is_emissive_only(&self) -> bool {
+        self.ambient == 0.
+            && self.diffuse == 0.
+            && self.specular == 0.
+            && self.emission != Color::black()
+    }
+
+    /// Scales ambient and diffuse down so that diffuse, reflective and
+    /// transparency never add up to more than 1.
+    pub fn energy_conserving(self) -> Self {
+        let total = self.diffuse + self.reflective + self.transparency;
+        if total <= 1. || self.diffuse == 0. {
+            return self;
+        }
+        let scale = (1. - self.reflective - self.transparency).max(0.) / self.diffuse;
+        Self {
+            ambient: self.ambient * scale,
+            diffuse: self.diffuse * scale,
+            ..self
+        }
+    }
+
     pub fn set_color(&mut self, c: Color) -> Self {
         self.color = c;
-        *self
+        self.clone()
     }
 
+    /// Clamped to [0, 1] so the material never returns more light than
+    /// it receives.
     pub fn set_ambient(&mut self, a: f64) -> Self {
-        self.ambient = a;
-        *self
+        self.ambient = a.clamp(0., 1.);
+        self.clone()
     }
 
+    /// Clamped to [0, 1].
     pub fn set_diffuse(&mut self, d: f64) -> Self {
-        self.diffuse = d;
-        *self
+        self.diffuse = d.clamp(0., 1.);
+        self.clone()
+    }
+
+    pub fn set_emission(&mut self, c: Color) -> Self {
+        self.emission = c;
+        self.clone()
     }
 
+    /// Clamped to [0, 1].
     pub fn set_specular(&mut self, s: f64) -> Self {
-        self.specular = s;
-        *self
+        self.specular = s.clamp(0., 1.);
+        self.clone()
     }
 
     pub fn set_shininess(&mut self, s: f64) -> Self {
         self.shininess = s;
-        *self
+        self.clone()
     }
 
+    pub fn set_fast_specular(&mut self, fast: bool) -> Self {
+        self.fast_specular = fast;
+        self.clone()
+    }
+
+    /// Clamped to [0, 1].
     pub fn set_reflective(&mut self, r: f64) -> Self {
-        self.reflective = r;
-        *self
+        self.reflective = r.clamp(0., 1.);
+        self.clone()
     }
 
+    pub fn set_reflective_pattern(&mut self, p: Pattern) -> Self {
+        self.reflective_pattern = Some(p);
+        self.clone()
+    }
+
+    /// Clamped to [0, 1].
+    pub fn set_roughness(&mut self, r: f64) -> Self {
+        self.roughness = r.clamp(0., 1.);
+        self.clone()
+    }
+
+    /// Clamped to [0, 1].
     pub fn set_transparency(&mut self, t: f64) -> Self {
-        self.transparency = t;
-        *self
+        self.transparency = t.clamp(0., 1.);
+        self.clone()
     }
 
     pub fn set_refractive_index(&mut self, ri: f64) -> Self {
         self.refractive_index = ri;
-        *self
+        self.clone()
+    }
+
+    pub fn set_bump(&mut self, p: Pattern) -> Self {
+        self.bump = Some(p);
+        self.clone()
     }
 
     pub fn set_pattern(&mut self, p: Pattern) -> Self {
         self.pattern = Some(p);
-        *self
+        self.clone()
+    }
+
+    pub fn set_double_sided(&mut self, double_sided: bool) -> Self {
+        self.double_sided = double_sided;
+        self.clone()
+    }
+
+    /// Clamped to [0, 1].
+    pub fn set_shadow_catcher(&mut self, opacity: f64) -> Self {
+        self.shadow_catcher = Some(opacity.clamp(0., 1.));
+        self.clone()
+    }
+}
+
+impl AlmostEq for &Material {
+    fn almost_eq(self, other: Self, eps: f64) -> bool {
+        self.color.almost_eq(other.color, eps)
+            && self.ambient.almost_eq(other.ambient, eps)
+            && self.emission.almost_eq(other.emission, eps)
+            && self.diffuse.almost_eq(other.diffuse, eps)
+            && self.specular.almost_eq(other.specular, eps)
+            && self.shininess.almost_eq(other.shininess, eps)
+            && self.fast_specular == other.fast_specular
+            && self.reflective.almost_eq(other.reflective, eps)
+            && self.reflective_pattern == other.reflective_pattern
+            && self.roughness.almost_eq(other.roughness, eps)
+            && self.transparency.almost_eq(other.transparency, eps)
+            && self.refractive_index.almost_eq(other.refractive_index, eps)
+            && self.pattern == other.pattern
+            && self.bump == other.bump
+            && self.double_sided == other.double_sided
+            && match (self.shadow_catcher, other.shadow_catcher) {
+                (Some(a), Some(b)) => a.almost_eq(b, eps),
+                (a, b) => a == b,
+            }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
+        assert_almost_eq,
         geometry::{Point, Vector},
         light::PointLight,
+        macros::EPSILON,
     };
 
     use super::*;
 
-    fn almost_eq(c1: Color, c2: Color) -> bool {
-        (c1.0 - c2.0) < 1e6 && (c1.1 - c2.1) < 1e6 && (c1.2 - c2.2) < 1e6
-    }
     #[test]
     fn lighting_with_the_eye_between_the_light_and_the_surface() {
         let object = Object::sphere();
@@ -139,11 +374,8 @@ mod tests {
         let position = Point(0., 0., 0.);
         let eyev = Vector(0., 0., -1.);
         let normalv = Vector(0., 0., -1.);
-        let light = PointLight {
-            position: Point(0., 0., -10.),
-            intensity: Color(1., 1., 1.),
-        };
-        let result = m.lighting(&object, light, position, eyev, normalv, false);
+        let light = PointLight::new(Point(0., 0., -10.), Color(1., 1., 1.));
+        let result = m.lighting(&object, &light, position, eyev, normalv, false);
         assert_eq!(result, Color(1.9, 1.9, 1.9));
     }
     #[test]
@@ -153,11 +385,8 @@ mod tests {
         let position = Point(0., 0., 0.);
         let eyev = Vector(0., 2f64.sqrt() / 2., -2f64.sqrt() / 2.);
         let normalv = Vector(0., 0., -1.);
-        let light = PointLight {
-            position: Point(0., 0., -10.),
-            intensity: Color(1., 1., 1.),
-        };
-        let result = m.lighting(&object, light, position, eyev, normalv, false);
+        let light = PointLight::new(Point(0., 0., -10.), Color(1., 1., 1.));
+        let result = m.lighting(&object, &light, position, eyev, normalv, false);
         assert_eq!(result, Color(1.0, 1.0, 1.0));
     }
     #[test]
@@ -167,12 +396,9 @@ mod tests {
         let position = Point(0., 0., 0.);
         let eyev = Vector(0., 0., -1.);
         let normalv = Vector(0., 0., -1.);
-        let light = PointLight {
-            position: Point(0., 10., -10.),
-            intensity: Color(1., 1., 1.),
-        };
-        let result = m.lighting(&object, light, position, eyev, normalv, false);
-        assert!(almost_eq(result, Color(0.7364, 0.7364, 0.7364)));
+        let light = PointLight::new(Point(0., 10., -10.), Color(1., 1., 1.));
+        let result = m.lighting(&object, &light, position, eyev, normalv, false);
+        assert_almost_eq!(result, Color(0.7364, 0.7364, 0.7364));
     }
     #[test]
     fn lighting_with_eye_in_the_path_of_the_reflection_vector() {
@@ -181,12 +407,9 @@ mod tests {
         let position = Point(0., 0., 0.);
         let eyev = Vector(0., -2f64.sqrt() / 2., -2f64.sqrt() / 2.);
         let normalv = Vector(0., 0., -1.);
-        let light = PointLight {
-            position: Point(0., 10., -10.),
-            intensity: Color(1., 1., 1.),
-        };
-        let result = m.lighting(&object, light, position, eyev, normalv, false);
-        assert!(almost_eq(result, Color(1.6364, 1.6364, 1.6364)));
+        let light = PointLight::new(Point(0., 10., -10.), Color(1., 1., 1.));
+        let result = m.lighting(&object, &light, position, eyev, normalv, false);
+        assert_almost_eq!(result, Color(1.6364, 1.6364, 1.6364));
     }
     #[test]
     fn lighting_with_the_light_behind_the_surface() {
@@ -195,11 +418,8 @@ mod tests {
         let position = Point(0., 0., 0.);
         let eyev = Vector(0., 0., -1.);
         let normalv = Vector(0., 0., -1.);
-        let light = PointLight {
-            position: Point(0., 0., 10.),
-            intensity: Color(1., 1., 1.),
-        };
-        let result = m.lighting(&object, light, position, eyev, normalv, false);
+        let light = PointLight::new(Point(0., 0., 10.), Color(1., 1., 1.));
+        let result = m.lighting(&object, &light, position, eyev, normalv, false);
         assert_eq!(result, Color(0.1, 0.1, 0.1));
     }
     #[test]
@@ -209,15 +429,40 @@ mod tests {
         let position = Point(0., 0., 0.);
         let eyev = Vector(0., 0., -1.);
         let normalv = Vector(0., 0., -1.);
-        let light = PointLight {
-            position: Point(0., 0., -10.),
-            intensity: Color(1., 1., 1.),
-        };
+        let light = PointLight::new(Point(0., 0., -10.), Color(1., 1., 1.));
         let in_shadow = true;
-        let result = m.lighting(&object, light, position, eyev, normalv, in_shadow);
+        let result = m.lighting(&object, &light, position, eyev, normalv, in_shadow);
         assert_eq!(result, Color(0.1, 0.1, 0.1));
     }
     #[test]
+    fn setters_clamp_coefficients_to_the_unit_interval() {
+        let m = Material::default()
+            .set_reflective(2.)
+            .set_transparency(-0.2)
+            .set_ambient(1.5)
+            .set_diffuse(-1.)
+            .set_specular(3.);
+        assert_eq!(m.reflective, 1.);
+        assert_eq!(m.transparency, 0.);
+        assert_eq!(m.ambient, 1.);
+        assert_eq!(m.diffuse, 0.);
+        assert_eq!(m.specular, 1.);
+    }
+    #[test]
+    fn setters_pass_valid_coefficients_through() {
+        let m = Material::default()
+            .set_reflective(0.3)
+            .set_transparency(0.7)
+            .set_ambient(0.)
+            .set_diffuse(1.)
+            .set_specular(0.25);
+        assert_eq!(m.reflective, 0.3);
+        assert_eq!(m.transparency, 0.7);
+        assert_eq!(m.ambient, 0.);
+        assert_eq!(m.diffuse, 1.);
+        assert_eq!(m.specular, 0.25);
+    }
+    #[test]
     fn lighting_with_a_pattern_applied() {
         let object = Object::sphere();
         let m = Material::default()
@@ -230,17 +475,72 @@ mod tests {
             .set_specular(0.);
         let eyev = Vector(0., 0., -1.);
         let normalv = Vector(0., 0., -1.);
-        let light = PointLight {
-            position: Point(0., 0., -10.),
-            intensity: Color(1., 1., 1.),
-        };
+        let light = PointLight::new(Point(0., 0., -10.), Color(1., 1., 1.));
         assert_eq!(
-            m.lighting(&object, light, Point(0.9, 0., 0.), eyev, normalv, false),
+            m.lighting(&object, &light, Point(0.9, 0., 0.), eyev, normalv, false),
             Color(1., 1., 1.)
         );
         assert_eq!(
-            m.lighting(&object, light, Point(1.1, 0., 0.), eyev, normalv, false),
+            m.lighting(&object, &light, Point(1.1, 0., 0.), eyev, normalv, false),
             Color(0., 0., 0.)
         );
     }
+
+    #[test]
+    fn energy_conserving_scales_ambient_and_diffuse() {
+        let m = Material::default().set_reflective(0.5).energy_conserving();
+        assert_almost_eq!(m.diffuse, 0.5);
+        assert_almost_eq!(m.ambient, 0.1 * 0.5 / 0.9);
+        assert_eq!(m.reflective, 0.5);
+        let plausible = Material::default().set_diffuse(0.5).set_reflective(0.5);
+        assert_eq!(plausible.clone().energy_conserving(), plausible);
+    }
+
+    #[test]
+    fn the_specular_power_fast_paths() {
+        let m = Material::default().set_shininess(200.);
+        for x in [0., 0.5, 0.9, 0.99, 0.999, 1.] {
+            assert!((m.specular_power(x) - x.powf(200.)).abs() < 1e-12);
+        }
+        let fast = Material::default()
+            .set_shininess(200.5)
+            .set_fast_specular(true);
+        assert_eq!(fast.specular_power(0.), 0.);
+        assert_eq!(fast.specular_power(1.), 1.);
+        assert!(fast.specular_power(0.99) < fast.specular_power(0.999));
+    }
+
+    #[test]
+    fn materials_survive_a_lossy_text_round_trip() {
+        let m = Material::default()
+            .set_color(Color(0.1, 2. / 3., 0.7))
+            .set_diffuse(1. / 3.)
+            .set_reflective(0.123456789)
+            .set_refractive_index(1.52);
+        let round = |x: f64| format!("{x:.6}").parse::<f64>().unwrap();
+        let Color(r, g, b) = m.color;
+        let loaded = Material {
+            color: Color(round(r), round(g), round(b)),
+            diffuse: round(m.diffuse),
+            reflective: round(m.reflective),
+            refractive_index: round(m.refractive_index),
+            ..m.clone()
+        };
+        assert_ne!(loaded, m);
+        assert_almost_eq!(&loaded, &m);
+        let shinier = Material {
+            shininess: 10.,
+            ..loaded
+        };
+        assert!(!shinier.almost_eq(&m, EPSILON));
+    }
+
+    #[test]
+    fn looking_up_material_presets() {
+        let glass = Material::preset("glass").unwrap();
+        assert_eq!(glass.transparency, 0.9);
+        assert_eq!(glass.refractive_index, 1.5);
+        assert!(Material::preset("gold").unwrap().reflective > 0.);
+        assert_eq!(Material::preset("unobtainium"), None);
+    }
 }
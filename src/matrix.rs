@@ -91,6 +91,47 @@ impl Matrix {
         }
         Matrix(minv)
     }
+
+    /// The entries in row-major order.
+    pub fn to_array(&self) -> [f64; 16] {
+        let mut a = [0.; 16];
+        for (i, row) in self.0.iter().enumerate() {
+            a[i * 4..i * 4 + 4].copy_from_slice(row);
+        }
+        a
+    }
+
+    pub fn from_array(a: [f64; 16]) -> Self {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row.copy_from_slice(&a[i * 4..i * 4 + 4]);
+        }
+        Self(m)
+    }
+
+    pub fn powi(self, n: u32) -> Self {
+        let mut result = Matrix::id();
+        let mut base = self;
+        let mut exp = n;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exp >>= 1;
+        }
+        result
+    }
+
+    pub fn transform_points(&self, points: &[Point]) -> Vec<Point> {
+        points.iter().map(|&p| *self * p).collect()
+    }
+
+    pub fn transform_points_in_place(&self, points: &mut [Point]) {
+        for p in points {
+            *p = *self * *p;
+        }
+    }
 }
 
 impl Mul for Matrix {
@@ -155,6 +196,24 @@ mod tests {
     use crate::{assert_almost_eq, geometry::Vector};
 
     use super::*;
+    #[test]
+    fn converting_a_matrix_to_and_from_a_flat_array() {
+        let a = [
+            1., 2., 3., 4., 5.5, 6.5, 7.5, 8.5, 9., 10., 11., 12., 13.5, 14.5, 15.5, 16.5,
+        ];
+        let m = Matrix::from_array(a);
+        assert_eq!(
+            m,
+            Matrix([
+                [1., 2., 3., 4.],
+                [5.5, 6.5, 7.5, 8.5],
+                [9., 10., 11., 12.],
+                [13.5, 14.5, 15.5, 16.5],
+            ])
+        );
+        assert_eq!(m.to_array(), a);
+    }
+
     #[test]
     fn multiplying_two_matrices() {
         let a = Matrix([
@@ -271,4 +330,32 @@ mod tests {
         ]);
         assert_almost_eq!(a.inverse(), b);
     }
+
+    #[test]
+    fn raising_a_matrix_to_a_power() {
+        let a = Matrix([
+            [1., 2., 3., 4.],
+            [5., 6., 7., 8.],
+            [9., 8., 7., 6.],
+            [5., 4., 3., 2.],
+        ]);
+        assert_eq!(a.powi(0), Matrix::id());
+        assert_eq!(a.powi(1), a);
+        assert_eq!(a.powi(5), a * a * a * a * a);
+    }
+
+    #[test]
+    fn transforming_many_points_at_once() {
+        let a = Matrix([
+            [1., 2., 3., 4.],
+            [2., 4., 4., 2.],
+            [8., 6., 4., 1.],
+            [0., 0., 0., 1.],
+        ]);
+        let mut points = vec![Point(1., 2., 3.), Point(-1., 0., 0.5), Point(0., 0., 0.)];
+        let expected: Vec<Point> = points.iter().map(|&p| a * p).collect();
+        assert_eq!(a.transform_points(&points), expected);
+        a.transform_points_in_place(&mut points);
+        assert_eq!(points, expected);
+    }
 }
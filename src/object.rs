@@ -1,218 +1,537 @@
+use std::{
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
 use crate::{
+    bounds::BoundingBox,
     canvas::Color,
     geometry::{Point, Vector},
     intersection::{Intersection, Intersections},
-    macros::EPSILON,
+    macros::{AlmostEq, EPSILON},
     material::Material,
-    pattern::Pattern,
+    pattern::{Pattern, UvMap},
     ray::Ray,
+    shape::{Implicit, Plane, Primitive, Sdf, Sphere, Torus},
     transform::{Transform, Transformable},
 };
 
 use uuid::Uuid;
 
-#[derive(Debug, PartialEq, Clone, Copy, Hash)]
+#[derive(Debug, Clone)]
 pub enum Shape {
     Sphere,
     Plane,
+    Torus(Torus),
+    Implicit(Implicit),
+    Custom(Arc<dyn Primitive>),
+}
+
+impl Shape {
+    pub fn primitive(&self) -> &dyn Primitive {
+        match self {
+            Shape::Sphere => &Sphere,
+            Shape::Plane => &Plane,
+            Shape::Torus(t) => t,
+            Shape::Implicit(i) => i,
+            Shape::Custom(p) => p.as_ref(),
+        }
+    }
+}
+
+// Custom primitives have no structural equality, they compare by identity.
+impl PartialEq for Shape {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Shape::Sphere, Shape::Sphere) | (Shape::Plane, Shape::Plane) => true,
+            (Shape::Torus(a), Shape::Torus(b)) => a == b,
+            (Shape::Implicit(a), Shape::Implicit(b)) => a == b,
+            (Shape::Custom(a), Shape::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+impl Hash for Shape {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Shape::Torus(t) => {
+                t.major_radius.to_bits().hash(state);
+                t.minor_radius.to_bits().hash(state);
+            }
+            Shape::Implicit(i) => Arc::as_ptr(&i.sdf).cast::<()>().hash(state),
+            Shape::Custom(p) => Arc::as_ptr(p).cast::<()>().hash(state),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Object {
-    pub shape: Shape,
-    pub transform: Transform,
+    // The shape and transform are only set through methods, which keep
+    // `bounding` in step.
+    shape: Shape,
+    transform: Transform,
     pub material: Material,
     pub uuid: Uuid,
+    /// Objects that don't cast shadows are skipped by shadow rays.
+    pub casts_shadow: bool,
+    /// A label for finding the object again, see `World::find_by_name`.
+    pub name: Option<String>,
+    /// How 2D patterns are wrapped around the object.
+    pub uv_map: UvMap,
+    /// Whether camera rays see the object.
+    pub visible_primary: bool,
+    /// Whether reflection, refraction and shadow rays see the object.
+    pub visible_secondary: bool,
+    // `bounding_sphere`, recomputed whenever the transform is set so
+    // `may_intersect` stays cheap.
+    bounding: (Point, f64),
 }
 
 impl<'a> Object {
     pub fn sphere() -> Self {
-        Self {
-            shape: Shape::Sphere,
-            transform: Transform::default(),
-            material: Material::default(),
-            uuid: Uuid::new_v4(),
-        }
+        Self::with_shape(Shape::Sphere, UvMap::Spherical)
+    }
+
+    /// A plane through `point` facing `normal`, instead of the xz plane.
+    pub fn plane_at(point: Point, normal: Vector) -> Self {
+        let up = Vector(0., 1., 0.);
+        let n = normal.normalize();
+        let axis = up.cross(n);
+        let rotation = if axis.magnitude() >= EPSILON {
+            let angle = up.dot(n).clamp(-1., 1.).acos();
+            Transform::rotation_about(Point(0., 0., 0.), axis, angle)
+        } else if n.1 > 0. {
+            Transform::default()
+        } else {
+            Transform::rotation_x(std::f64::consts::PI)
+        };
+        Object::plane().set_transform(Transform::translation(point.0, point.1, point.2) * rotation)
     }
 
     pub fn glass_sphere() -> Self {
         Self {
-            shape: Shape::Sphere,
-            transform: Transform::default(),
             material: Material {
                 transparency: 1.,
                 refractive_index: 1.5,
                 ..Material::default()
             },
-            uuid: Uuid::new_v4(),
+            ..Self::sphere()
         }
     }
 
     pub fn plane() -> Self {
-        Self {
-            shape: Shape::Plane,
+        Self::with_shape(Shape::Plane, UvMap::Planar)
+    }
+
+    pub fn torus(major_radius: f64, minor_radius: f64) -> Self {
+        let torus = Torus {
+            major_radius,
+            minor_radius,
+        };
+        Self::with_shape(Shape::Torus(torus), UvMap::Cylindrical)
+    }
+
+    pub fn implicit(sdf: Arc<Sdf>) -> Self {
+        Self::with_shape(Shape::Implicit(Implicit::new(sdf)), UvMap::Planar)
+    }
+
+    pub fn custom(primitive: Arc<dyn Primitive>) -> Self {
+        Self::with_shape(Shape::Custom(primitive), UvMap::Planar)
+    }
+
+    fn with_shape(shape: Shape, uv_map: UvMap) -> Self {
+        let mut object = Self {
+            shape,
             transform: Transform::default(),
             material: Material::default(),
             uuid: Uuid::new_v4(),
-        }
+            casts_shadow: true,
+            name: None,
+            uv_map,
+            visible_primary: true,
+            visible_secondary: true,
+            bounding: (Point(0., 0., 0.), f64::INFINITY),
+        };
+        object.bounding = object.compute_bounding_sphere();
+        object
     }
 
+    pub fn shape(&self) -> &Shape {
+        &self.shape
+    }
+
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Replaces the current transform with `t`.
     pub fn set_transform(&mut self, t: Transform) -> Self {
         self.transform = t;
-        *self
+        self.bounding = self.compute_bounding_sphere();
+        self.clone()
+    }
+
+    /// Applies `t` after the current transform, i.e. `t * self.transform`.
+    pub fn compose_transform(&mut self, t: Transform) -> Self {
+        self.transform = t * self.transform;
+        self.bounding = self.compute_bounding_sphere();
+        self.clone()
     }
 
     pub fn set_material(&mut self, m: Material) -> Self {
         self.material = m;
-        *self
+        self.clone()
     }
 
     pub fn set_color(&mut self, c: Color) -> Self {
         self.material.color = c;
-        *self
+        self.clone()
     }
 
     pub fn set_ambient(&mut self, a: f64) -> Self {
-        self.material.ambient = a;
-        *self
+        self.material.set_ambient(a);
+        self.clone()
     }
 
     pub fn set_diffuse(&mut self, d: f64) -> Self {
-        self.material.diffuse = d;
-        *self
+        self.material.set_diffuse(d);
+        self.clone()
+    }
+
+    pub fn set_emission(&mut self, c: Color) -> Self {
+        self.material.emission = c;
+        self.clone()
     }
 
     pub fn set_specular(&mut self, s: f64) -> Self {
-        self.material.specular = s;
-        *self
+        self.material.set_specular(s);
+        self.clone()
     }
 
     pub fn set_shininess(&mut self, s: f64) -> Self {
         self.material.shininess = s;
-        *self
+        self.clone()
     }
 
     pub fn set_reflective(&mut self, r: f64) -> Self {
-        self.material.reflective = r;
-        *self
+        self.material.set_reflective(r);
+        self.clone()
+    }
+
+    pub fn set_reflective_pattern(&mut self, p: Pattern) -> Self {
+        self.material.set_reflective_pattern(p);
+        self.clone()
+    }
+
+    pub fn set_roughness(&mut self, r: f64) -> Self {
+        self.material.set_roughness(r);
+        self.clone()
     }
 
     pub fn set_transparency(&mut self, t: f64) -> Self {
-        self.material.transparency = t;
-        *self
+        self.material.set_transparency(t);
+        self.clone()
     }
 
     pub fn set_refractive_index(&mut self, ri: f64) -> Self {
         self.material.refractive_index = ri;
-        *self
+        self.clone()
+    }
+
+    pub fn set_bump(&mut self, p: Pattern) -> Self {
+        self.material.set_bump(p);
+        self.clone()
     }
 
     pub fn set_pattern(&mut self, p: Pattern) -> Self {
         self.material.pattern = Some(p);
-        *self
+        self.clone()
+    }
+
+    pub fn set_double_sided(&mut self, double_sided: bool) -> Self {
+        self.material.double_sided = double_sided;
+        self.clone()
+    }
+
+    pub fn set_casts_shadow(&mut self, casts_shadow: bool) -> Self {
+        self.casts_shadow = casts_shadow;
+        self.clone()
+    }
+
+    pub fn set_name(&mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self.clone()
+    }
+
+    pub fn set_visible_primary(&mut self, visible: bool) -> Self {
+        self.visible_primary = visible;
+        self.clone()
+    }
+
+    pub fn set_visible_secondary(&mut self, visible: bool) -> Self {
+        self.visible_secondary = visible;
+        self.clone()
+    }
+
+    pub fn set_uv_map(&mut self, uv_map: UvMap) -> Self {
+        self.uv_map = uv_map;
+        self.clone()
+    }
+
+    pub fn set_shadow_catcher(&mut self, opacity: f64) -> Self {
+        self.material.set_shadow_catcher(opacity);
+        self.clone()
     }
 
     pub fn intersect(&'a self, ray: Ray) -> Intersections<'a> {
-        let local_ray = ray.transform(self.transform.inverse());
-        match self.shape {
-            Shape::Sphere => {
-                let mut xs: Intersections<'a> = Intersections(Vec::with_capacity(2));
-                let sphere_to_ray = local_ray.origin - Point(0., 0., 0.);
-                let a = local_ray.direction.dot(local_ray.direction);
-                let b = 2f64 * local_ray.direction.dot(sphere_to_ray);
-                let c = sphere_to_ray.dot(sphere_to_ray) - 1.;
-                let discriminant = b.powi(2) - 4. * a * c;
-
-                if discriminant < 0. {
-                    xs
-                } else {
-                    xs.push(Intersection {
-                        t: (-b - discriminant.sqrt()) / (2. * a),
-                        object: &self,
-                    });
-
-                    xs.push(Intersection {
-                        t: (-b + discriminant.sqrt()) / (2. * a),
-                        object: &self,
-                    });
-                    xs
-                }
-            }
-            Shape::Plane => {
-                let mut xs: Intersections = Intersections(Vec::with_capacity(1));
-                if local_ray.direction.1.abs() >= EPSILON {
-                    xs.push(Intersection {
-                        t: -local_ray.origin.1 / local_ray.direction.1,
-                        object: &self,
-                    })
-                }
-                xs
-            }
+        let local_ray = self.local_ray(ray);
+        let mut xs: Vec<Intersection<'a>> = self
+            .shape
+            .primitive()
+            .local_intersect(local_ray)
+            .into_iter()
+            .map(|t| Intersection { t, object: self })
+            .collect();
+        xs.sort_unstable();
+        Intersections(xs)
+    }
+
+    pub fn bounds(&self) -> BoundingBox {
+        self.shape.primitive().bounds().transform(self.transform)
+    }
+
+    /// A sphere around `bounds`, as a center and radius. Unbounded shapes
+    /// get an infinite radius.
+    pub fn bounding_sphere(&self) -> (Point, f64) {
+        self.bounding
+    }
+
+    fn compute_bounding_sphere(&self) -> (Point, f64) {
+        let b = self.bounds();
+        if !b.is_finite() {
+            return (Point(0., 0., 0.), f64::INFINITY);
         }
+        (b.center(), (b.max - b.min).magnitude() / 2.)
     }
 
-    pub fn normal_at(self, p: Point) -> Vector {
-        let local_point = self.transform.minv * p;
-        let local_normal: Vector;
-        match self.shape {
-            Shape::Sphere => {
-                local_normal = local_point - Point(0., 0., 0.);
-            }
-            Shape::Plane => {
-                local_normal = Vector(0., 1., 0.);
-            }
+    /// Cheap first test for `intersect`: false when the line along `ray`
+    /// misses the bounding sphere, so the ray can't hit the object.
+    pub fn may_intersect(&self, ray: Ray) -> bool {
+        let (center, radius) = self.bounding_sphere();
+        if radius.is_infinite() {
+            return true;
+        }
+        let to_center = center - ray.origin;
+        let t = to_center.dot(ray.direction) / ray.direction.dot(ray.direction);
+        let closest = to_center - ray.direction * t;
+        closest.dot(closest) <= radius * radius
+    }
+
+    /// Whether the object blocks `ray` somewhere in `[0, max_t)`, without
+    /// building intersections. Shadow rays already start `shadow_bias` off
+    /// the surface, as in `World::shadow_factors`. Always false for objects
+    /// that don't cast shadows or are hidden from secondary rays.
+    pub fn occludes(&self, ray: Ray, max_t: f64) -> bool {
+        if !self.casts_shadow || !self.visible_secondary {
+            return false;
         }
+        let local_ray = self.local_ray(ray);
+        (self.shape.primitive().local_intersect(local_ray))
+            .into_iter()
+            .any(|t| t >= 0. && t < max_t)
+    }
+
+    pub fn normal_at(&self, p: Point) -> Vector {
+        if self.transform.is_identity() {
+            return self.shape.primitive().local_normal_at(p).normalize();
+        }
+        let local_point = self.transform.minv * p;
+        let local_normal = self.shape.primitive().local_normal_at(local_point);
         let world_normal = self.transform.minv.transpose() * local_normal;
         world_normal.normalize()
     }
+
+    // Skipping the identity transform is only a shortcut; the result is
+    // the same either way.
+    // The direction is deliberately left unnormalized: `t` along the local
+    // ray then equals `t` along `ray`, so hits compare directly against
+    // world-space distances even under non-uniform scaling.
+    fn local_ray(&self, ray: Ray) -> Ray {
+        if self.transform.is_identity() {
+            ray
+        } else {
+            ray.transform(self.transform.inverse())
+        }
+    }
+}
+
+impl AlmostEq for &Object {
+    fn almost_eq(self, other: Self, eps: f64) -> bool {
+        self.shape == other.shape
+            && self.transform.almost_eq(other.transform, eps)
+            && self.material.almost_eq(&other.material, eps)
+            && self.casts_shadow == other.casts_shadow
+            && self.name == other.name
+            && self.uv_map == other.uv_map
+            && self.visible_primary == other.visible_primary
+            && self.visible_secondary == other.visible_secondary
+    }
 }
 #[cfg(test)]
 mod tests {
-    use std::f64::consts::PI;
+    use std::{f64::consts::PI, sync::Arc};
 
     use super::{Object, Shape};
+    use crate::assert_almost_eq;
+    use crate::camera::Camera;
+    use crate::canvas::Color;
     use crate::geometry::{Point, Vector};
     use crate::intersection::Intersections;
+    use crate::macros::{AlmostEq, EPSILON};
     use crate::material::Material;
     use crate::matrix::Matrix;
+    use crate::pattern::UvMap;
     use crate::ray::Ray;
+    use crate::shape::{Primitive, SmoothUnion, Sphere};
     use crate::transform::Transform;
+    use crate::world::World;
 
-    fn almost_eq(v1: Vector, v2: Vector) -> bool {
-        (v1.0 - v2.0).abs() < 1e6 && (v1.1 - v2.1).abs() < 1e6 && (v1.2 - v2.2).abs() < 1e6
+    #[test]
+    fn sphere_hits_near_the_origin_of_the_ray_keep_their_precision() {
+        // The origin sits 2^-40 outside the sphere, so c is computed exactly
+        // and any error comes from solving the quadratic.
+        let origin = Point(0., 0., -(1. + 2f64.powi(-40)));
+        let direction = Vector(0.1, 0.2, 0.9);
+        let xs = Sphere.local_intersect(Ray::new(origin, direction));
+        let near = 1.0105496686361556e-12;
+        assert!(((xs[0] - near) / near).abs() < 1e-12);
+        assert!((xs[1] - 2.0930232558148463).abs() < 1e-12);
+
+        let o = origin - Point(0., 0., 0.);
+        let (a, b, c) = (
+            direction.dot(direction),
+            2. * direction.dot(o),
+            o.dot(o) - 1.,
+        );
+        let naive = (-b - (b * b - 4. * a * c).sqrt()) / (2. * a);
+        assert!(((naive - near) / near).abs() > 1e-6);
+    }
+    #[test]
+    fn the_bounds_of_transformed_objects() {
+        let s = Object::sphere().set_transform(Transform::translation(1., 0., 0.));
+        assert_eq!(s.bounds().min, Point(0., -1., -1.));
+        assert_eq!(s.bounds().max, Point(2., 1., 1.));
+        let t = Object::torus(2., 0.5).set_transform(Transform::scaling(1., 2., 1.));
+        assert_eq!(t.bounds().min, Point(-2.5, -1., -2.5));
+        assert!(!Object::plane().bounds().is_finite());
+    }
+    #[test]
+    fn identity_transforms_take_the_same_results_on_the_fast_path() {
+        let fast = Object::sphere();
+        // A transform equal to the identity but not detected as one, so the
+        // matrix multiplies still happen.
+        let slow = Object::sphere().set_transform(Transform::scaling(1., 1., 1. + 1e-15));
+        assert!(fast.transform.is_identity() && !slow.transform.is_identity());
+        let r = Ray::new(Point(0.2, 0.3, -5.), Vector(0., 0., 1.));
+        let (Intersections(a), Intersections(b)) = (fast.intersect(r), slow.intersect(r));
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(&b) {
+            assert_almost_eq!(a.t, b.t);
+        }
+        let p = Point(0., 0.6, -0.8);
+        assert_almost_eq!(fast.normal_at(p), slow.normal_at(p));
     }
     #[test]
     fn a_default_sphere() {
         let s = Object::sphere();
         let uuid = s.uuid;
-        assert_eq!(
-            s,
-            Object {
+        assert_almost_eq!(
+            &s,
+            &Object {
                 shape: Shape::Sphere,
                 transform: Transform {
                     m: Matrix::id(),
                     minv: Matrix::id()
                 },
                 material: Material::default(),
-                uuid
+                uuid,
+                casts_shadow: true,
+                name: None,
+                uv_map: UvMap::Spherical,
+                visible_primary: true,
+                visible_secondary: true,
+                bounding: s.bounding,
             }
         );
     }
     #[test]
+    fn the_bounding_sphere_follows_the_transform() {
+        let mut s = Object::sphere().set_transform(Transform::scaling(2., 2., 2.));
+        assert_almost_eq!(s.bounding_sphere().1, 2. * 3f64.sqrt());
+        s.compose_transform(Transform::translation(0., 5., 0.));
+        let (center, radius) = s.bounding_sphere();
+        assert_almost_eq!(center, Point(0., 5., 0.));
+        assert_almost_eq!(radius, 2. * 3f64.sqrt());
+        assert!(!s.may_intersect(Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.))));
+        assert!(Object::plane().bounding_sphere().1.is_infinite());
+    }
+    #[test]
     fn changing_a_spheres_transformation() {
         let mut s = Object::sphere();
         let uuid = s.uuid;
         let t = Transform::translation(2., 3., 4.);
         s.set_transform(t);
-        assert_eq!(
-            s,
-            Object {
+        assert_almost_eq!(
+            &s,
+            &Object {
                 shape: Shape::Sphere,
                 transform: t,
                 material: Material::default(),
-                uuid
+                uuid,
+                casts_shadow: true,
+                name: None,
+                uv_map: UvMap::Spherical,
+                visible_primary: true,
+                visible_secondary: true,
+                bounding: s.bounding,
             }
-        )
+        );
+    }
+    #[test]
+    fn objects_differing_by_a_tiny_transform_are_almost_equal() {
+        let a = Object::sphere().set_transform(Transform::translation(1., 2., 3.));
+        let b = Object::sphere().set_transform(Transform::translation(1. + 1e-9, 2., 3.));
+        assert_ne!(a.transform, b.transform);
+        assert_almost_eq!(&a, &b);
+        let c = Object::plane().set_transform(Transform::translation(1., 2., 3.));
+        assert!(!a.almost_eq(&c, 1e-4));
+    }
+    #[test]
+    fn object_setters_clamp_material_coefficients() {
+        let s = Object::sphere().set_reflective(2.).set_transparency(-0.5);
+        assert_eq!(s.material.reflective, 1.);
+        assert_eq!(s.material.transparency, 0.);
+    }
+    #[test]
+    fn composing_transforms_applies_them_in_order() {
+        let scaling = Transform::scaling(2., 2., 2.);
+        let translation = Transform::translation(2., 3., 4.);
+        let composed = Object::sphere()
+            .compose_transform(scaling)
+            .compose_transform(translation);
+        let replaced = Object::sphere().set_transform(translation * scaling);
+        assert_eq!(composed.transform, replaced.transform);
+    }
+    #[test]
+    fn setting_a_transform_replaces_the_previous_one() {
+        let translation = Transform::translation(2., 3., 4.);
+        let s = Object::sphere()
+            .set_transform(Transform::scaling(2., 2., 2.))
+            .set_transform(translation);
+        assert_eq!(s.transform, translation);
     }
     #[test]
     fn intersecting_a_translated_sphere_with_a_ray() {
@@ -276,20 +595,20 @@ mod tests {
     fn computing_the_normal_on_a_translated_sphere() {
         let mut s = Object::sphere();
         s.set_transform(Transform::translation(0., 1., 0.));
-        assert!(almost_eq(
+        assert_almost_eq!(
             s.normal_at(Point(0., 1.70711, -0.70711)),
             Vector(0., 0.70711, -0.70711)
-        ));
+        );
     }
     #[test]
     fn computing_the_normal_on_a_transformed_sphere() {
         let mut s = Object::sphere();
         let t = Transform::scaling(1., 0.5, 1.) * Transform::rotation_z(PI / 5.);
         s.set_transform(t);
-        assert!(almost_eq(
+        assert_almost_eq!(
             s.normal_at(Point(0., 2f64.sqrt() / 2., -2f64.sqrt() / 2.)),
             Vector(0., 0.97014, -0.24254)
-        ));
+        );
     }
     #[test]
     fn the_normal_of_a_plane_is_constant_everywhere() {
@@ -322,6 +641,161 @@ mod tests {
         let Intersections(xs) = p.intersect(r);
         assert_eq!(xs.len(), 0);
     }
+    #[derive(Debug)]
+    struct Disc;
+
+    impl Primitive for Disc {
+        fn local_intersect(&self, ray: Ray) -> Vec<f64> {
+            if ray.direction.1.abs() < EPSILON {
+                return vec![];
+            }
+            let t = -ray.origin.1 / ray.direction.1;
+            let p = ray.position(t);
+            if p.0.powi(2) + p.2.powi(2) <= 1. {
+                vec![t]
+            } else {
+                vec![]
+            }
+        }
+
+        fn local_normal_at(&self, _point: Point) -> Vector {
+            Vector(0., 1., 0.)
+        }
+    }
+
+    #[test]
+    fn intersecting_a_custom_primitive() {
+        let disc: Arc<dyn Primitive> = Arc::new(Disc);
+        let d = Object::custom(disc.clone()).set_transform(Transform::translation(0., 1., 0.));
+        let r = Ray::new(Point(0.5, 5., 0.), Vector(0., -1., 0.));
+        let Intersections(xs) = d.intersect(r);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 4.);
+        assert_eq!(d.normal_at(Point(0.5, 1., 0.)), Vector(0., 1., 0.));
+        let miss = Ray::new(Point(1.5, 5., 0.), Vector(0., -1., 0.));
+        assert_eq!(d.intersect(miss).0.len(), 0);
+        assert_eq!(d.shape, Shape::Custom(disc));
+        assert_ne!(d.shape, Shape::Custom(Arc::new(Disc)));
+        assert_ne!(d.shape, Shape::Plane);
+    }
+
+    #[test]
+    fn rendering_a_custom_primitive() {
+        let mut w = World::empty();
+        w.unlit_fallback = true;
+        w.add_object(Object::custom(Arc::new(Disc)).set_color(Color::red()));
+        let t = Transform::view_transform(Point(0., 5., 0.), Point(0., 0., 0.), Vector(0., 0., 1.));
+        let c = Camera::new(11, 11, PI / 2., Some(t));
+        let image = c.render(&w);
+        assert_eq!(image.pixel_at(5, 5), Color::red());
+        assert_eq!(image.pixel_at(0, 0), Color::black());
+    }
+
+    #[test]
+    fn a_ray_through_the_hole_of_a_torus_hits_it_four_times() {
+        let t = Object::torus(1., 0.25);
+        let r = Ray::new(Point(-5., 0., 0.), Vector(1., 0., 0.));
+        let Intersections(xs) = t.intersect(r);
+        assert_eq!(xs.len(), 4);
+        for (x, expected) in xs.iter().zip([3.75, 4.25, 5.75, 6.25]) {
+            assert_almost_eq!(x.t, expected, 1e-9);
+        }
+    }
+
+    #[test]
+    fn a_ray_along_the_axis_of_a_torus_misses_it() {
+        let t = Object::torus(1., 0.25);
+        let r = Ray::new(Point(0., 5., 0.), Vector(0., -1., 0.));
+        assert_eq!(t.intersect(r).0.len(), 0);
+    }
+
+    #[test]
+    fn intersecting_a_transformed_torus_at_an_angle() {
+        let t = Object::torus(2., 0.5).set_transform(Transform::rotation_x(PI / 2.));
+        let r = Ray::new(Point(2., 0., 5.), Vector(0., 0., -1.));
+        let Intersections(xs) = t.intersect(r);
+        assert_eq!(xs.len(), 2);
+        assert_almost_eq!(xs[0].t, 4.5, 1e-9);
+        assert_almost_eq!(xs[1].t, 5.5, 1e-9);
+    }
+
+    #[test]
+    fn the_normal_on_a_torus() {
+        let t = Object::torus(1., 0.25);
+        assert_almost_eq!(t.normal_at(Point(1.25, 0., 0.)), Vector(1., 0., 0.));
+        assert_almost_eq!(t.normal_at(Point(0.75, 0., 0.)), Vector(-1., 0., 0.));
+        assert_almost_eq!(t.normal_at(Point(0., 0.25, 1.)), Vector(0., 1., 0.));
+    }
+
+    fn unit_sphere_sdf(p: Point) -> f64 {
+        (p - Point(0., 0., 0.)).magnitude() - 1.
+    }
+
+    #[test]
+    fn intersecting_an_implicit_sphere_matches_the_analytic_sphere() {
+        let implicit = Object::implicit(Arc::new(unit_sphere_sdf))
+            .set_transform(Transform::translation(0., 1., 0.) * Transform::scaling(2., 2., 2.));
+        let analytic = Object::sphere()
+            .set_transform(Transform::translation(0., 1., 0.) * Transform::scaling(2., 2., 2.));
+        for r in [
+            Ray::new(Point(0., 1., -5.), Vector(0., 0., 1.)),
+            Ray::new(Point(0.5, 1.3, -5.), Vector(0., 0., 1.)),
+            Ray::new(Point(-4., 3., -4.), Vector(1., -0.5, 1.).normalize()),
+        ] {
+            let Intersections(xs) = implicit.intersect(r);
+            let Intersections(expected) = analytic.intersect(r);
+            assert_eq!(xs.len(), 1);
+            assert_almost_eq!(xs[0].t, expected[0].t, 1e-6);
+            let p = r.position(xs[0].t);
+            assert_almost_eq!(implicit.normal_at(p), analytic.normal_at(p), 1e-6);
+        }
+        let miss = Ray::new(Point(0., 4., -5.), Vector(0., 0., 1.));
+        assert_eq!(implicit.intersect(miss).0.len(), 0);
+    }
+
+    // Distances to two unit spheres overlapping around the origin, which
+    // meet in a sharp seam along the plane x = 0.
+    fn two_spheres(p: Point) -> (f64, f64) {
+        let a = (p - Point(-0.8, 0., 0.)).magnitude() - 1.;
+        let b = (p - Point(0.8, 0., 0.)).magnitude() - 1.;
+        (a, b)
+    }
+
+    #[test]
+    fn a_smooth_union_rounds_the_seam_between_two_spheres() {
+        let hard = Object::implicit(Arc::new(|p| {
+            let (a, b) = two_spheres(p);
+            a.min(b)
+        }));
+        let blended = |k: f64| {
+            Object::implicit(Arc::new(move |p| {
+                let (a, b) = two_spheres(p);
+                SmoothUnion { k }.distance(a, b)
+            }))
+        };
+        let sharp = blended(0.);
+        let smooth = blended(0.5);
+        let seam = Ray::new(Point(0., 5., 0.), Vector(0., -1., 0.));
+        let t = |o: &Object| o.intersect(seam).0[0].t;
+        assert_almost_eq!(t(&hard), 5. - 0.6, 1e-6);
+        assert_eq!(t(&sharp), t(&hard));
+        assert!(t(&smooth) < t(&hard) - 0.05);
+        // Away from the seam the blend leaves the spheres alone.
+        let side = Ray::new(Point(5., 0., 0.), Vector(-1., 0., 0.));
+        assert_almost_eq!(smooth.intersect(side).0[0].t, 3.2, 1e-6);
+    }
+
+    #[test]
+    fn a_plane_placed_through_a_point_with_a_normal() {
+        let p = Object::plane_at(Point(0., 0., 10.), Vector(0., 0., -1.));
+        let r = Ray::new(Point(0., 0., 0.), Vector(0., 0., 1.));
+        let Intersections(xs) = p.intersect(r);
+        assert_eq!(xs.len(), 1);
+        assert_almost_eq!(xs[0].t, 10.);
+        assert_almost_eq!(p.normal_at(Point(3., -2., 10.)), Vector(0., 0., -1.));
+        let ceiling = Object::plane_at(Point(0., 4., 0.), Vector(0., -2., 0.));
+        assert_almost_eq!(ceiling.normal_at(Point(1., 4., 1.)), Vector(0., -1., 0.));
+    }
     #[test]
     fn a_ray_intersecting_a_plane_from_above() {
         let p = Object::plane();
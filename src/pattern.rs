@@ -1,27 +1,115 @@
+use std::{f64::consts::PI, sync::Arc};
+
 use crate::{
     canvas::Color,
     geometry::Point,
     object::Object,
+    texture::ImageTexture,
     transform::{Transform, Transformable},
 };
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Pattern {
     pattern: PatternType,
     transform: Transform,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 enum PatternType {
     Stripe(Color, Color),
     Gradient(Color, Color),
+    SrgbGradient(Color, Color),
     Ring(Color, Color),
     Checkers(Color, Color),
+    UvCheckers(f64, f64, Color, Color),
+    Grid(Color, Color, f64),
+    Image(Arc<ImageTexture>),
     Test,
 }
 
+fn planar_map(p: Point) -> (f64, f64) {
+    (p.0.rem_euclid(1.), p.2.rem_euclid(1.))
+}
+
+/// How an object's points are mapped to UV coordinates for 2D textures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UvMap {
+    /// x and z, tiled once per unit square.
+    Planar,
+    /// Longitude and latitude around the y axis.
+    Spherical,
+    /// Angle around the y axis, and y tiled once per unit.
+    Cylindrical,
+    /// Position on the face of the unit cube, see `cube_map`.
+    Cube,
+}
+
+impl UvMap {
+    pub fn map(&self, p: Point) -> (f64, f64) {
+        let around_y = || 1. - (p.0.atan2(p.2) / (2. * PI) + 0.5);
+        match self {
+            UvMap::Planar => planar_map(p),
+            UvMap::Spherical => {
+                let radius = (p - Point(0., 0., 0.)).magnitude();
+                (around_y(), 1. - (p.1 / radius).acos() / PI)
+            }
+            UvMap::Cylindrical => (around_y(), p.1.rem_euclid(1.)),
+            UvMap::Cube => {
+                let (_, u, v) = cube_map(p);
+                (u, v)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    Front,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl CubeFace {
+    /// The face of the unit cube a point lies on, picked by its dominant axis.
+    pub fn from_point(p: Point) -> Self {
+        let coord = p.0.abs().max(p.1.abs()).max(p.2.abs());
+        if coord == p.0 {
+            CubeFace::Right
+        } else if coord == -p.0 {
+            CubeFace::Left
+        } else if coord == p.1 {
+            CubeFace::Up
+        } else if coord == -p.1 {
+            CubeFace::Down
+        } else if coord == p.2 {
+            CubeFace::Front
+        } else {
+            CubeFace::Back
+        }
+    }
+}
+
+/// Maps a point on the unit cube to its face and a `(u, v)` pair in [0, 1]
+/// on that face, with v pointing up when looking at the face from outside.
+pub fn cube_map(p: Point) -> (CubeFace, f64, f64) {
+    let wrap = |c: f64| c.rem_euclid(2.) / 2.;
+    let face = CubeFace::from_point(p);
+    let (u, v) = match face {
+        CubeFace::Front => (wrap(p.0 + 1.), wrap(p.1 + 1.)),
+        CubeFace::Back => (wrap(1. - p.0), wrap(p.1 + 1.)),
+        CubeFace::Left => (wrap(p.2 + 1.), wrap(p.1 + 1.)),
+        CubeFace::Right => (wrap(1. - p.2), wrap(p.1 + 1.)),
+        CubeFace::Up => (wrap(p.0 + 1.), wrap(1. - p.2)),
+        CubeFace::Down => (wrap(p.0 + 1.), wrap(p.2 + 1.)),
+    };
+    (face, u, v)
+}
+
 impl Pattern {
-    pub fn pattern_at(self, p: Point) -> Color {
+    pub fn pattern_at(&self, p: Point) -> Color {
         match self.pattern {
             PatternType::Stripe(a, b) => {
                 if p.0.floor() as isize % 2 == 0 {
@@ -35,6 +123,11 @@ impl Pattern {
                 let fraction = p.0 - (p.0.floor());
                 a + distance * fraction
             }
+            PatternType::SrgbGradient(a, b) => {
+                let (a, b) = (a.to_srgb(), b.to_srgb());
+                let fraction = p.0 - (p.0.floor());
+                (a + (b - a) * fraction).to_linear()
+            }
             PatternType::Ring(a, b) => {
                 if (p.0.powi(2) + p.2.powi(2)).sqrt().floor() as isize % 2 == 0 {
                     a
@@ -49,19 +142,63 @@ impl Pattern {
                     b
                 }
             }
+            PatternType::Grid(line, background, thickness) => {
+                let near_line = |c: f64| (c - c.round()).abs() <= thickness;
+                if near_line(p.0) || near_line(p.2) {
+                    line
+                } else {
+                    background
+                }
+            }
+            PatternType::UvCheckers(..) | PatternType::Image(_) => {
+                let (u, v) = planar_map(p);
+                self.uv_pattern_at(u, v)
+            }
             PatternType::Test => Color(p.0, p.1, p.2),
         }
     }
 
-    pub fn pattern_at_object(self, object: &Object, world_point: Point) -> Color {
-        let object_point = world_point.transform(object.transform.inverse());
-        let pattern_point = object_point.transform(self.transform.inverse());
-        self.pattern_at(pattern_point)
+    fn uv_pattern_at(&self, u: f64, v: f64) -> Color {
+        match self.pattern {
+            PatternType::UvCheckers(width, height, a, b) => {
+                if ((u * width).floor() + (v * height).floor()) as isize % 2 == 0 {
+                    a
+                } else {
+                    b
+                }
+            }
+            PatternType::Image(ref texture) => texture.uv_pattern_at(u, v),
+            _ => unreachable!("not a UV pattern"),
+        }
+    }
+
+    /// Whether the pattern is a solid texture evaluated directly on 3D
+    /// points, as opposed to a 2D texture evaluated on UV coordinates.
+    pub fn is_solid(&self) -> bool {
+        !matches!(
+            self.pattern,
+            PatternType::UvCheckers(..) | PatternType::Image(_)
+        )
+    }
+
+    /// 2D patterns are mapped onto the object with its `uv_map`.
+    pub fn pattern_at_object(&self, object: &Object, world_point: Point) -> Color {
+        let object_point = world_point.transform(object.transform().inverse());
+        if self.is_solid() {
+            return self.pattern_at_transformed(object_point);
+        }
+        let (u, v) = (object.uv_map).map(object_point.transform(self.transform.inverse()));
+        self.uv_pattern_at(u, v)
+    }
+
+    /// Samples the pattern at `p` after applying the pattern's own transform.
+    pub fn pattern_at_transformed(&self, p: Point) -> Color {
+        self.pattern_at(p.transform(self.transform.inverse()))
     }
 
     pub fn set_transform(&mut self, t: Transform) -> Self {
         self.transform = t;
-        *self
+        self.clone()
     }
 
     pub fn stripe_pattern(a: Color, b: Color) -> Self {
@@ -78,6 +215,15 @@ impl Pattern {
         }
     }
 
+    /// Like `gradient_pattern`, but blends the colors in sRGB space, which
+    /// looks more even to the eye than blending linear values.
+    pub fn gradient_pattern_srgb(a: Color, b: Color) -> Self {
+        Self {
+            pattern: PatternType::SrgbGradient(a, b),
+            transform: Transform::default(),
+        }
+    }
+
     pub fn ring_pattern(a: Color, b: Color) -> Self {
         Self {
             pattern: PatternType::Ring(a, b),
@@ -85,6 +231,8 @@ impl Pattern {
         }
     }
 
+    /// Solid checkers alternating along x, y and z. On curved surfaces the
+    /// cells are cut by the surface, so use `checkers_2d` for a regular grid.
     pub fn checkers_pattern(a: Color, b: Color) -> Self {
         Self {
             pattern: PatternType::Checkers(a, b),
@@ -92,6 +240,34 @@ impl Pattern {
         }
     }
 
+    /// Checkers drawn in UV space with `width` x `height` cells per unit
+    /// square. On its own the pattern maps x and z as planar coordinates;
+    /// on an object it uses the object's `uv_map`.
+    pub fn checkers_2d(width: f64, height: f64, a: Color, b: Color) -> Self {
+        Self {
+            pattern: PatternType::UvCheckers(width, height, a, b),
+            transform: Transform::default(),
+        }
+    }
+
+    /// Lines of `line_color` along integer x and z coordinates, drawn
+    /// `thickness` to each side of the line, over `bg_color`.
+    pub fn grid(line_color: Color, bg_color: Color, thickness: f64) -> Self {
+        Self {
+            pattern: PatternType::Grid(line_color, bg_color, thickness),
+            transform: Transform::default(),
+        }
+    }
+
+    /// An image texture, tiled over the x and z coordinates with one copy
+    /// per unit square, or mapped with the object's `uv_map`.
+    pub fn image(texture: Arc<ImageTexture>) -> Self {
+        Self {
+            pattern: PatternType::Image(texture),
+            transform: Transform::default(),
+        }
+    }
+
     pub fn test_pattern() -> Self {
         Self {
             pattern: PatternType::Test,
@@ -102,7 +278,7 @@ impl Pattern {
 
 #[cfg(test)]
 mod tests {
-    use crate::{object::Object, transform::Transform};
+    use crate::{assert_almost_eq, macros::AlmostEq, object::Object, transform::Transform};
 
     use super::*;
     const WHITE: Color = Color(1., 1., 1.);
@@ -176,6 +352,15 @@ mod tests {
         );
     }
     #[test]
+    fn an_srgb_gradient_interpolates_perceptually() {
+        let pattern = Pattern::gradient_pattern_srgb(Color::black(), Color::white());
+        assert_eq!(pattern.pattern_at(Point(0., 0., 0.)), Color::black());
+        let mid = pattern.pattern_at(Point(0.5, 0., 0.));
+        assert_almost_eq!(mid.to_srgb(), Color(0.5, 0.5, 0.5));
+        assert_almost_eq!(mid, Color(0.21404, 0.21404, 0.21404));
+        assert!(pattern.pattern_at(Point(0.999, 0., 0.)).0 > 0.99);
+    }
+    #[test]
     fn a_ring_should_extend_in_both_x_and_z() {
         let pattern = Pattern::ring_pattern(Color::white(), Color::black());
         assert_eq!(pattern.pattern_at(Point(0., 0., 0.)), Color::white());
@@ -204,4 +389,127 @@ mod tests {
         assert_eq!(pattern.pattern_at(Point(0., 0., 0.99)), Color::white());
         assert_eq!(pattern.pattern_at(Point(0., 0., 1.01)), Color::black());
     }
+    #[test]
+    fn solid_checkers_alternate_in_all_three_axes() {
+        let pattern = Pattern::checkers_pattern(WHITE, BLACK);
+        assert!(pattern.is_solid());
+        assert_eq!(pattern.pattern_at(Point(0.5, 0.5, 0.5)), WHITE);
+        assert_eq!(pattern.pattern_at(Point(1.5, 0.5, 0.5)), BLACK);
+        assert_eq!(pattern.pattern_at(Point(0.5, 1.5, 0.5)), BLACK);
+        assert_eq!(pattern.pattern_at(Point(0.5, 0.5, 1.5)), BLACK);
+    }
+    #[test]
+    fn uv_checkers_do_not_depend_on_y_when_mapped_planar() {
+        let pattern = Pattern::checkers_2d(2., 2., WHITE, BLACK);
+        assert!(!pattern.is_solid());
+        assert_eq!(pattern.pattern_at(Point(0.25, 0., 0.25)), WHITE);
+        assert_eq!(pattern.pattern_at(Point(0.25, 1.5, 0.25)), WHITE);
+        assert_eq!(pattern.pattern_at(Point(0.75, 0., 0.25)), BLACK);
+        assert_eq!(pattern.pattern_at(Point(0.75, -7.2, 0.25)), BLACK);
+        assert_eq!(pattern.pattern_at(Point(0.75, 0., 0.75)), WHITE);
+        assert_eq!(pattern.pattern_at(Point(-0.25, 0., 0.25)), BLACK);
+    }
+
+    #[test]
+    fn a_grid_draws_lines_near_integer_x_and_z() {
+        let pattern = Pattern::grid(BLACK, WHITE, 0.05);
+        assert_eq!(pattern.pattern_at(Point(0., 0., 0.5)), BLACK);
+        assert_eq!(pattern.pattern_at(Point(2.03, 0., 0.5)), BLACK);
+        assert_eq!(pattern.pattern_at(Point(0.5, 0., -0.98)), BLACK);
+        assert_eq!(pattern.pattern_at(Point(0.5, 3., 0.5)), WHITE);
+        assert_eq!(pattern.pattern_at(Point(1.2, 0., 0.9)), WHITE);
+    }
+
+    #[test]
+    fn the_thickness_of_grid_lines_is_configurable() {
+        let thin = Pattern::grid(BLACK, WHITE, 0.01);
+        let thick = Pattern::grid(BLACK, WHITE, 0.2);
+        let p = Point(1.1, 0., 0.5);
+        assert_eq!(thin.pattern_at(p), WHITE);
+        assert_eq!(thick.pattern_at(p), BLACK);
+    }
+
+    #[test]
+    fn identifying_the_face_of_a_cube_from_a_point() {
+        let cases = [
+            (Point(-1., 0.5, -0.25), CubeFace::Left),
+            (Point(1.1, -0.75, 0.8), CubeFace::Right),
+            (Point(0.1, 0.6, 0.9), CubeFace::Front),
+            (Point(-0.7, 0., -2.), CubeFace::Back),
+            (Point(0.5, 1., 0.9), CubeFace::Up),
+            (Point(-0.2, -1.3, 1.1), CubeFace::Down),
+        ];
+        for (p, face) in cases {
+            assert_eq!(CubeFace::from_point(p), face);
+        }
+    }
+
+    #[test]
+    fn uv_mapping_the_faces_of_a_cube() {
+        let cases = [
+            (Point(-0.5, 0.5, 1.), CubeFace::Front, 0.25, 0.75),
+            (Point(0.5, -0.5, 1.), CubeFace::Front, 0.75, 0.25),
+            (Point(0.5, 0.5, -1.), CubeFace::Back, 0.25, 0.75),
+            (Point(-0.5, -0.5, -1.), CubeFace::Back, 0.75, 0.25),
+            (Point(-1., 0.5, -0.5), CubeFace::Left, 0.25, 0.75),
+            (Point(-1., -0.5, 0.5), CubeFace::Left, 0.75, 0.25),
+            (Point(1., 0.5, 0.5), CubeFace::Right, 0.25, 0.75),
+            (Point(1., -0.5, -0.5), CubeFace::Right, 0.75, 0.25),
+            (Point(-0.5, 1., -0.5), CubeFace::Up, 0.25, 0.75),
+            (Point(0.5, 1., 0.5), CubeFace::Up, 0.75, 0.25),
+            (Point(-0.5, -1., 0.5), CubeFace::Down, 0.25, 0.75),
+            (Point(0.5, -1., -0.5), CubeFace::Down, 0.75, 0.25),
+        ];
+        for (p, face, u, v) in cases {
+            assert_eq!(cube_map(p), (face, u, v));
+        }
+    }
+
+    #[test]
+    fn an_image_pattern_maps_the_texture_over_xz() {
+        let mut canvas = crate::canvas::Canvas::new(2, 2, Some(BLACK));
+        canvas.write_pixel(1, 0, WHITE);
+        let pattern = Pattern::image(Arc::new(ImageTexture::new(canvas)));
+        assert!(!pattern.is_solid());
+        assert_eq!(pattern.pattern_at(Point(0.9, 0., 0.9)), WHITE);
+        assert_eq!(pattern.pattern_at(Point(1.9, 5., -0.1)), WHITE);
+        assert_eq!(pattern.pattern_at(Point(0.1, 0., 0.9)), BLACK);
+    }
+
+    #[test]
+    fn the_uv_map_of_the_object_picks_the_texels() {
+        let mut canvas = crate::canvas::Canvas::new(4, 4, None);
+        for y in 0..4 {
+            for x in 0..4 {
+                canvas.write_pixel(x, y, Color(x as f64 / 3., y as f64 / 3., 0.));
+            }
+        }
+        let pattern = Pattern::image(Arc::new(ImageTexture::new(canvas)));
+        let sphere = Object::sphere();
+        assert_eq!(sphere.uv_map, UvMap::Spherical);
+        let planar = Object::sphere().set_uv_map(UvMap::Planar);
+        let p = Point(0.6, 0., -0.8);
+        assert_ne!(
+            pattern.pattern_at_object(&sphere, p),
+            pattern.pattern_at_object(&planar, p)
+        );
+        assert_eq!(pattern.pattern_at_object(&planar, p), pattern.pattern_at(p));
+    }
+
+    #[test]
+    fn spherical_and_cylindrical_uv_maps() {
+        let cases = [
+            (Point(0., 0., -1.), 0., 0.5),
+            (Point(1., 0., 0.), 0.25, 0.5),
+            (Point(0., 0., 1.), 0.5, 0.5),
+            (Point(-1., 0., 0.), 0.75, 0.5),
+            (Point(0., 1., 0.), 0.5, 1.),
+        ];
+        for (p, u, v) in cases {
+            let (mu, mv) = UvMap::Spherical.map(p);
+            assert!((mu - u).abs() < 1e-9 && (mv - v).abs() < 1e-9, "{p:?}");
+        }
+        let (u, v) = UvMap::Cylindrical.map(Point(1., 1.25, 0.));
+        assert!((u - 0.25).abs() < 1e-9 && (v - 0.25).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,45 @@
+/// Maps a seed and an index to a number in [0, 1) with the SplitMix64
+/// finalizer. Stateless, so the same inputs always give the same value no
+/// matter the order or thread they are evaluated on.
+pub fn jitter(seed: u64, index: u64) -> f64 {
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The `index`th element of the van der Corput sequence in `base`.
+pub fn halton(mut index: usize, base: usize) -> f64 {
+    let mut result = 0.;
+    let mut f = 1.;
+    while index > 0 {
+        f /= base as f64;
+        result += f * (index % base) as f64;
+        index /= base;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_is_deterministic_and_in_range() {
+        for i in 0..100 {
+            let j = jitter(7, i);
+            assert!((0. ..1.).contains(&j));
+            assert_eq!(j, jitter(7, i));
+        }
+        assert_ne!(jitter(1, 0), jitter(2, 0));
+    }
+
+    #[test]
+    fn the_halton_sequence() {
+        let base2: Vec<f64> = (1..5).map(|i| halton(i, 2)).collect();
+        assert_eq!(base2, vec![0.5, 0.25, 0.75, 0.125]);
+        let base3: Vec<f64> = (1..4).map(|i| halton(i, 3)).collect();
+        assert_eq!(base3, vec![1. / 3., 2. / 3., 1. / 9.]);
+    }
+}
@@ -1,2 +1,281 @@
+use std::{
+    fmt::{self, Debug},
+    sync::Arc,
+};
 
+use crate::{
+    bounds::BoundingBox,
+    geometry::{Point, Vector},
+    macros::EPSILON,
+    ray::Ray,
+};
 
+/// A shape expressed in its own object space. `Object` takes care of the
+/// transform, so implementors only deal with untransformed rays and points.
+pub trait Primitive: Debug + Send + Sync {
+    fn local_intersect(&self, ray: Ray) -> Vec<f64>;
+    fn local_normal_at(&self, point: Point) -> Vector;
+
+    /// Whether the shape encloses a volume. Open surfaces, like planes, are
+    /// treated as thin interfaces when tracking refractive indices.
+    fn is_closed(&self) -> bool {
+        true
+    }
+
+    /// Object-space bounds. Defaults to an infinite box.
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::infinite()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sphere;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Plane;
+
+impl Primitive for Sphere {
+    fn local_intersect(&self, ray: Ray) -> Vec<f64> {
+        let sphere_to_ray = ray.origin - Point(0., 0., 0.);
+        let a = ray.direction.dot(ray.direction);
+        let b = 2f64 * ray.direction.dot(sphere_to_ray);
+        let c = sphere_to_ray.dot(sphere_to_ray) - 1.;
+        solve_quadratic(a, b, c)
+    }
+
+    fn local_normal_at(&self, point: Point) -> Vector {
+        point - Point(0., 0., 0.)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(Point(-1., -1., -1.), Point(1., 1., 1.))
+    }
+}
+
+impl Primitive for Plane {
+    fn local_intersect(&self, ray: Ray) -> Vec<f64> {
+        if ray.direction.1.abs() >= EPSILON {
+            vec![-ray.origin.1 / ray.direction.1]
+        } else {
+            vec![]
+        }
+    }
+
+    fn local_normal_at(&self, _point: Point) -> Vector {
+        Vector(0., 1., 0.)
+    }
+
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(
+            Point(f64::NEG_INFINITY, 0., f64::NEG_INFINITY),
+            Point(f64::INFINITY, 0., f64::INFINITY),
+        )
+    }
+}
+
+/// A torus lying in the xz plane around the y axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Torus {
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl Primitive for Torus {
+    fn local_intersect(&self, ray: Ray) -> Vec<f64> {
+        let (o, d) = (ray.origin - Point(0., 0., 0.), ray.direction);
+        let r2 = self.major_radius.powi(2);
+        let k = r2 + self.minor_radius.powi(2);
+        let dd = d.dot(d);
+        let od = o.dot(d);
+        let e = o.dot(o) - k;
+        solve_quartic(
+            dd * dd,
+            4. * dd * od,
+            2. * dd * e + 4. * od * od + 4. * r2 * d.1 * d.1,
+            4. * od * e + 8. * r2 * o.1 * d.1,
+            e * e - 4. * r2 * (self.minor_radius.powi(2) - o.1 * o.1),
+        )
+    }
+
+    fn local_normal_at(&self, point: Point) -> Vector {
+        let ring = Vector(point.0, 0., point.2).normalize() * self.major_radius;
+        point - (Point(0., 0., 0.) + ring)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let (outer, r) = (self.major_radius + self.minor_radius, self.minor_radius);
+        BoundingBox::new(Point(-outer, -r, -outer), Point(outer, r, outer))
+    }
+}
+
+/// Real roots of `c4 x^4 + c3 x^3 + c2 x^2 + c1 x + c0`, in increasing
+/// order, using Ferrari's method followed by a few Newton iterations.
+pub fn solve_quartic(c4: f64, c3: f64, c2: f64, c1: f64, c0: f64) -> Vec<f64> {
+    let (a, b, c, d) = (c3 / c4, c2 / c4, c1 / c4, c0 / c4);
+    // Depressed quartic y^4 + p y^2 + q y + r with x = y - a / 4.
+    let p = b - 3. * a * a / 8.;
+    let q = c - a * b / 2. + a.powi(3) / 8.;
+    let r = d - a * c / 4. + a * a * b / 16. - 3. * a.powi(4) / 256.;
+
+    let mut ys = Vec::with_capacity(4);
+    if q.abs() < 1e-12 {
+        for z in solve_quadratic(1., p, r) {
+            if z >= 0. {
+                ys.push(z.sqrt());
+                ys.push(-z.sqrt());
+            }
+        }
+    } else {
+        let m = largest_cubic_root(p, p * p / 4. - r, -q * q / 8.);
+        let s = (2. * m).sqrt();
+        ys.extend(solve_quadratic(1., -s, p / 2. + m + q / (2. * s)));
+        ys.extend(solve_quadratic(1., s, p / 2. + m - q / (2. * s)));
+    }
+
+    let f = |x: f64| (((x + a) * x + b) * x + c) * x + d;
+    let df = |x: f64| ((4. * x + 3. * a) * x + 2. * b) * x + c;
+    let mut roots: Vec<f64> = ys
+        .into_iter()
+        .map(|y| {
+            let mut x = y - a / 4.;
+            for _ in 0..3 {
+                let slope = df(x);
+                if slope == 0. {
+                    break;
+                }
+                x -= f(x) / slope;
+            }
+            x
+        })
+        .collect();
+    roots.sort_unstable_by(|x, y| x.total_cmp(y));
+    roots
+}
+
+/// Real roots of `a x^2 + b x + c`, smallest first. Both roots are
+/// returned for a tangent, so callers always get zero or two values.
+///
+/// `(-b ± sqrt(d)) / 2a` cancels catastrophically for the root where `b`
+/// and `sqrt(d)` nearly cancel, e.g. for rays starting just off a surface.
+/// That root is taken from `c / q` instead, with `q` the non-cancelling sum.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return vec![];
+    }
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    if q == 0. {
+        return vec![0., 0.];
+    }
+    let (x1, x2) = (q / a, c / q);
+    vec![x1.min(x2), x1.max(x2)]
+}
+
+// Largest real root of m^3 + a m^2 + b m + c.
+fn largest_cubic_root(a: f64, b: f64, c: f64) -> f64 {
+    let p = b - a * a / 3.;
+    let q = 2. * a.powi(3) / 27. - a * b / 3. + c;
+    let discriminant = q * q / 4. + p.powi(3) / 27.;
+    let z = if discriminant >= 0. {
+        let sqrt = discriminant.sqrt();
+        (-q / 2. + sqrt).cbrt() + (-q / 2. - sqrt).cbrt()
+    } else {
+        let rho = (-p / 3.).sqrt();
+        let theta = (-q / (2. * rho.powi(3))).clamp(-1., 1.).acos();
+        2. * rho * (theta / 3.).cos()
+    };
+    z - a / 3.
+}
+
+pub type Sdf = dyn Fn(Point) -> f64 + Send + Sync;
+
+/// A surface defined by a signed distance function, intersected by sphere
+/// tracing. Only the first crossing along the ray is reported.
+#[derive(Clone)]
+pub struct Implicit {
+    pub sdf: Arc<Sdf>,
+    pub max_distance: f64,
+}
+
+impl Implicit {
+    const MAX_STEPS: usize = 256;
+    const HIT_DISTANCE: f64 = 1e-7;
+    const NORMAL_DELTA: f64 = 1e-5;
+
+    pub fn new(sdf: Arc<Sdf>) -> Self {
+        Self {
+            sdf,
+            max_distance: 100.,
+        }
+    }
+}
+
+impl Debug for Implicit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Implicit")
+            .field("sdf", &Arc::as_ptr(&self.sdf))
+            .field("max_distance", &self.max_distance)
+            .finish()
+    }
+}
+
+impl PartialEq for Implicit {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.sdf, &other.sdf) && self.max_distance == other.max_distance
+    }
+}
+
+/// Joins two signed distance functions like a union, but blends the
+/// surfaces over a band about `k` wide where they meet instead of leaving
+/// a crease. `k = 0` is the hard union.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothUnion {
+    pub k: f64,
+}
+
+impl SmoothUnion {
+    /// The polynomial smooth minimum of the distances `a` and `b`.
+    pub fn distance(self, a: f64, b: f64) -> f64 {
+        if self.k <= 0. {
+            return a.min(b);
+        }
+        let h = (0.5 + 0.5 * (b - a) / self.k).clamp(0., 1.);
+        b + (a - b) * h - self.k * h * (1. - h)
+    }
+}
+
+impl Primitive for Implicit {
+    fn local_intersect(&self, ray: Ray) -> Vec<f64> {
+        // March along the normalized direction, then convert the travelled
+        // distance back into the ray's own parametrization.
+        let length = ray.direction.magnitude();
+        let direction = ray.direction / length;
+        let mut s = 0.;
+        for _ in 0..Self::MAX_STEPS {
+            let d = (self.sdf)(ray.origin + direction * s).abs();
+            if d < Self::HIT_DISTANCE {
+                return vec![s / length];
+            }
+            s += d;
+            if s > self.max_distance {
+                break;
+            }
+        }
+        vec![]
+    }
+
+    fn local_normal_at(&self, point: Point) -> Vector {
+        let h = Self::NORMAL_DELTA;
+        let sdf = &self.sdf;
+        Vector(
+            sdf(point + Vector(h, 0., 0.)) - sdf(point - Vector(h, 0., 0.)),
+            sdf(point + Vector(0., h, 0.)) - sdf(point - Vector(0., h, 0.)),
+            sdf(point + Vector(0., 0., h)) - sdf(point - Vector(0., 0., h)),
+        )
+        .normalize()
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+
+use crate::canvas::{Canvas, Color};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureSampling {
+    Nearest,
+    Bilinear,
+}
+
+/// How UV coordinates outside [0, 1] are brought back onto the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    Clamp,
+    Repeat,
+    Mirror,
+}
+
+impl WrapMode {
+    fn wrap(self, c: f64) -> f64 {
+        match self {
+            WrapMode::Clamp => c.clamp(0., 1.),
+            WrapMode::Repeat => c.rem_euclid(1.),
+            WrapMode::Mirror => {
+                let c = c.rem_euclid(2.);
+                if c > 1. {
+                    2. - c
+                } else {
+                    c
+                }
+            }
+        }
+    }
+}
+
+/// An image mapped onto the unit square of UV space, with v pointing up.
+pub struct ImageTexture {
+    pub canvas: Canvas,
+    pub sampling: TextureSampling,
+    pub wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(canvas: Canvas) -> Self {
+        Self {
+            canvas,
+            sampling: TextureSampling::Nearest,
+            wrap: WrapMode::Clamp,
+        }
+    }
+
+    pub fn set_sampling(mut self, sampling: TextureSampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn set_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn uv_pattern_at(&self, u: f64, v: f64) -> Color {
+        let (u, v) = (self.wrap.wrap(u), self.wrap.wrap(v));
+        // Texel centers sit on integer coordinates, so u = 0 and u = 1 fall
+        // on the first and last columns.
+        let x = u * (self.canvas.width() - 1) as f64;
+        let y = (1. - v) * (self.canvas.height() - 1) as f64;
+        match self.sampling {
+            TextureSampling::Nearest => self.texel(x.round(), y.round()),
+            TextureSampling::Bilinear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let top = self.texel(x0, y0) * (1. - fx) + self.texel(x0 + 1., y0) * fx;
+                let bottom =
+                    self.texel(x0, y0 + 1.) * (1. - fx) + self.texel(x0 + 1., y0 + 1.) * fx;
+                top * (1. - fy) + bottom * fy
+            }
+        }
+    }
+
+    fn texel(&self, x: f64, y: f64) -> Color {
+        let x = x.clamp(0., (self.canvas.width() - 1) as f64) as usize;
+        let y = y.clamp(0., (self.canvas.height() - 1) as f64) as usize;
+        self.canvas.pixel_at(x, y)
+    }
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("width", &self.canvas.width())
+            .field("height", &self.canvas.height())
+            .field("sampling", &self.sampling)
+            .field("wrap", &self.wrap)
+            .finish()
+    }
+}
+
+impl PartialEq for ImageTexture {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_almost_eq;
+    use crate::macros::AlmostEq;
+
+    // A 3x2 image: black, red, white on the top row, green, blue, black
+    // on the bottom one.
+    fn texture() -> ImageTexture {
+        let mut canvas = Canvas::new(3, 2, None);
+        canvas.write_pixel(1, 0, Color::red());
+        canvas.write_pixel(2, 0, Color::white());
+        canvas.write_pixel(0, 1, Color::green());
+        canvas.write_pixel(1, 1, Color::blue());
+        ImageTexture::new(canvas)
+    }
+
+    #[test]
+    fn nearest_sampling_picks_the_closest_texel() {
+        let t = texture();
+        assert_eq!(t.uv_pattern_at(0., 1.), Color::black());
+        assert_eq!(t.uv_pattern_at(0.45, 0.9), Color::red());
+        assert_eq!(t.uv_pattern_at(1., 1.), Color::white());
+        assert_eq!(t.uv_pattern_at(0.1, 0.), Color::green());
+    }
+
+    #[test]
+    fn bilinear_sampling_interpolates_between_texels() {
+        let nearest = texture();
+        let bilinear = texture().set_sampling(TextureSampling::Bilinear);
+        for (u, v) in [(0., 0.), (0.5, 1.), (1., 0.), (0.5, 0.)] {
+            assert_eq!(bilinear.uv_pattern_at(u, v), nearest.uv_pattern_at(u, v));
+        }
+        let between = bilinear.uv_pattern_at(0.75, 1.);
+        assert_almost_eq!(between, (Color::red() + Color::white()) * 0.5);
+        let between_rows = bilinear.uv_pattern_at(0.5, 0.5);
+        assert_almost_eq!(between_rows, (Color::red() + Color::blue()) * 0.5);
+    }
+
+    // A 5x1 strip with a different color in each column.
+    fn strip() -> ImageTexture {
+        let mut canvas = Canvas::new(5, 1, None);
+        for x in 0..5 {
+            canvas.write_pixel(x, 0, Color(x as f64 / 4., 0., 0.));
+        }
+        ImageTexture::new(canvas)
+    }
+
+    #[test]
+    fn repeat_wrapping_tiles_the_texture() {
+        let t = strip().set_wrap(WrapMode::Repeat);
+        assert_eq!(t.uv_pattern_at(1.25, 0.5), t.uv_pattern_at(0.25, 0.5));
+        assert_eq!(t.uv_pattern_at(-0.75, 0.5), t.uv_pattern_at(0.25, 0.5));
+    }
+
+    #[test]
+    fn clamp_wrapping_stretches_the_edges() {
+        let t = strip();
+        assert_eq!(t.wrap, WrapMode::Clamp);
+        assert_eq!(t.uv_pattern_at(1.25, 0.5), Color(1., 0., 0.));
+        assert_eq!(t.uv_pattern_at(-3., 0.5), Color(0., 0., 0.));
+    }
+
+    #[test]
+    fn mirror_wrapping_reflects_the_texture() {
+        let t = strip().set_wrap(WrapMode::Mirror);
+        assert_eq!(t.uv_pattern_at(1.25, 0.5), t.uv_pattern_at(0.75, 0.5));
+        assert_eq!(t.uv_pattern_at(-0.25, 0.5), t.uv_pattern_at(0.25, 0.5));
+        assert_eq!(t.uv_pattern_at(2.25, 0.5), t.uv_pattern_at(0.25, 0.5));
+    }
+}
@@ -20,6 +20,17 @@ impl Default for Transform {
 }
 
 impl Transform {
+    pub fn is_identity(&self) -> bool {
+        self.m == Matrix::id()
+    }
+
+    /// Whether `minv` really is the inverse of `m`. Degenerate transforms,
+    /// like a scaling by zero, end up with infinite or NaN entries.
+    pub fn is_invertible(&self) -> bool {
+        self.minv.0.iter().flatten().all(|x| x.is_finite())
+            && (self.m * self.minv).almost_eq(Matrix::id(), 1e-6)
+    }
+
     pub fn translation(x: f64, y: f64, z: f64) -> Self {
         Self {
             m: Matrix([
@@ -111,6 +122,51 @@ impl Transform {
         Self { m, minv }
     }
 
+    /// Scaling that keeps `center` fixed instead of the origin.
+    pub fn scaling_about(center: Point, x: f64, y: f64, z: f64) -> Self {
+        Transform::about(center, Transform::scaling(x, y, z))
+    }
+
+    /// Rotation by `angle` around the line through `center` along `axis`,
+    /// counterclockwise when looking down the axis towards `center`.
+    pub fn rotation_about(center: Point, axis: Vector, angle: f64) -> Self {
+        let Vector(x, y, z) = axis.normalize();
+        let (sin, cos) = angle.sin_cos();
+        let k = 1. - cos;
+        let m = Matrix([
+            [
+                cos + x * x * k,
+                x * y * k - z * sin,
+                x * z * k + y * sin,
+                0.,
+            ],
+            [
+                y * x * k + z * sin,
+                cos + y * y * k,
+                y * z * k - x * sin,
+                0.,
+            ],
+            [
+                z * x * k - y * sin,
+                z * y * k + x * sin,
+                cos + z * z * k,
+                0.,
+            ],
+            [0., 0., 0., 1.],
+        ]);
+        let rotation = Self {
+            m,
+            minv: m.transpose(),
+        };
+        Transform::about(center, rotation)
+    }
+
+    fn about(center: Point, t: Transform) -> Self {
+        Transform::translation(center.0, center.1, center.2)
+            * t
+            * Transform::translation(-center.0, -center.1, -center.2)
+    }
+
     pub fn view_transform(from: Point, to: Point, up: Vector) -> Self {
         let forward = (to - from).normalize();
         let upn = up.normalize();
@@ -146,12 +202,60 @@ impl Transform {
         orientation * Transform::translation(-from.0, -from.1, -from.2)
     }
 
+    /// Object-to-world transform placing an object at `from` with its
+    /// local -z axis pointing at `to`. This is the inverse of the camera's
+    /// view transform.
+    pub fn look_at(from: Point, to: Point, up: Vector) -> Self {
+        Transform::view_transform(from, to, up).inverse()
+    }
+
     pub fn inverse(self) -> Self {
         Self {
             m: self.minv,
             minv: self.m,
         }
     }
+
+    /// Wraps an arbitrary invertible matrix, computing its inverse once.
+    pub fn from_matrix(m: Matrix) -> Self {
+        Self {
+            m,
+            minv: m.inverse(),
+        }
+    }
+
+    /// Splits the transform into a translation, a rotation matrix and a
+    /// scale, so that it equals translation * rotation * scaling. Shearing
+    /// is not recovered; a mirroring ends up as a negative x scale.
+    pub fn decompose(&self) -> (Vector, Matrix, Vector) {
+        let m = self.m.0;
+        let column = |j: usize| Vector(m[0][j], m[1][j], m[2][j]);
+        let (c0, c1, c2) = (column(0), column(1), column(2));
+        let mirrored = c0.cross(c1).dot(c2) < 0.;
+        let sx = if mirrored {
+            -c0.magnitude()
+        } else {
+            c0.magnitude()
+        };
+        let scale = Vector(sx, c1.magnitude(), c2.magnitude());
+        let (r0, r1, r2) = (c0 / scale.0, c1 / scale.1, c2 / scale.2);
+        let rotation = Matrix([
+            [r0.0, r1.0, r2.0, 0.],
+            [r0.1, r1.1, r2.1, 0.],
+            [r0.2, r1.2, r2.2, 0.],
+            [0., 0., 0., 1.],
+        ]);
+        (column(3), rotation, scale)
+    }
+
+    pub fn powi(self, n: i32) -> Self {
+        let base = if n < 0 { self.inverse() } else { self };
+        let exp = n.unsigned_abs();
+        Self {
+            m: base.m.powi(exp),
+            minv: base.minv.powi(exp),
+        }
+    }
 }
 
 impl Mul for Transform {
@@ -265,6 +369,19 @@ mod tests {
     use crate::geometry::{Point, Vector};
     use crate::matrix::Matrix;
 
+    #[test]
+    fn building_a_transform_from_a_matrix() {
+        let m = Matrix::from_array([
+            -5., 2., 6., -8., 1., -5., 1., 8., 7., 7., -6., -7., 1., -3., 7., 4.,
+        ]);
+        let t = Transform::from_matrix(m);
+        assert_eq!(t.m, m);
+        assert_almost_eq!(t.minv * m, Matrix::id());
+        assert_eq!(t.m.to_array(), m.to_array());
+        let r = Transform::rotation_y(PI / 3.) * Transform::translation(1., 2., 3.);
+        assert_almost_eq!(Transform::from_matrix(r.m), r);
+    }
+
     #[test]
     fn translating_a_point() {
         let p = Point(1., 2., 3.);
@@ -290,6 +407,23 @@ mod tests {
         assert_eq!(v.scaling(2., 3., 4.), Vector(-8., 18., 32.));
     }
     #[test]
+    fn scaling_about_a_point_keeps_it_fixed() {
+        let t = Transform::scaling_about(Point(2., 0., 0.), 2., 2., 2.);
+        assert_eq!(Point(2., 0., 0.).transform(t), Point(2., 0., 0.));
+        assert_eq!(Point(3., 0., 0.).transform(t), Point(4., 0., 0.));
+        assert_eq!(Point(4., 0., 0.).transform(t.inverse()), Point(3., 0., 0.));
+    }
+    #[test]
+    fn rotating_about_an_arbitrary_axis() {
+        let center = Point(1., 1., 0.);
+        let t = Transform::rotation_about(center, Vector(0., 0., 2.), PI / 2.);
+        assert_almost_eq!(center.transform(t), center);
+        assert_almost_eq!(Point(2., 1., 0.).transform(t), Point(1., 2., 0.));
+        let about_x = Transform::rotation_about(Point(0., 0., 0.), Vector(1., 0., 0.), 0.3);
+        assert_almost_eq!(about_x, Transform::rotation_x(0.3));
+        assert_almost_eq!(t.m * t.minv, Matrix::id());
+    }
+    #[test]
     fn rotating_a_point_around_the_x_axis() {
         let p = Point(0., 1., 0.);
         assert_almost_eq!(
@@ -385,4 +519,39 @@ mod tests {
             1e-4f64
         );
     }
+
+    #[test]
+    fn look_at_aims_the_local_forward_axis_at_the_target() {
+        let from = Point(1., 3., 2.);
+        let to = Point(4., -2., 8.);
+        let t = Transform::look_at(from, to, Vector(1., 1., 0.));
+        assert_almost_eq!(Point(0., 0., 0.).transform(t), from);
+        assert_almost_eq!(Vector(0., 0., -1.).transform(t), (to - from).normalize());
+        assert_almost_eq!(Point(0., 0., -(to - from).magnitude()).transform(t), to);
+    }
+
+    #[test]
+    fn raising_a_transform_to_a_power() {
+        let t = Transform::translation(1., 0., 0.5)
+            * Transform::rotation_y(PI / 6.)
+            * Transform::scaling(1.1, 1.1, 1.1);
+        assert_almost_eq!(t.powi(3), t * t * t);
+        assert_almost_eq!(t.powi(-1), t.inverse());
+        assert_almost_eq!(t.powi(0), Transform::default());
+        assert_almost_eq!(t.powi(-2), t.inverse() * t.inverse());
+    }
+
+    #[test]
+    fn decomposing_a_transform_recovers_its_parts() {
+        let t = Transform::translation(1., -2., 3.)
+            * Transform::rotation_about(Point(0., 0., 0.), Vector(1., 1., 0.), PI / 3.)
+            * Transform::scaling(-2., 0.5, 3.);
+        let (translation, rotation, scale) = t.decompose();
+        assert_almost_eq!(translation, Vector(1., -2., 3.));
+        assert_almost_eq!(scale, Vector(-2., 0.5, 3.));
+        let rebuilt = Transform::translation(translation.0, translation.1, translation.2)
+            * Transform::from_matrix(rotation)
+            * Transform::scaling(scale.0, scale.1, scale.2);
+        assert_almost_eq!(rebuilt, t);
+    }
 }
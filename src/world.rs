@@ -1,169 +1,827 @@
+use std::{borrow::Cow, cell::Cell, f64::consts::PI, fmt};
+
 use crate::{
+    bounds::BoundingBox,
+    camera::Camera,
     canvas::Color,
-    geometry::Point,
+    geometry::{Point, Vector},
     intersection::{Computations, Intersections},
-    light::PointLight,
+    light::{EnvironmentLight, PointLight},
+    macros::{AlmostEq, EPSILON},
     material::Material,
     object::Object,
     ray::Ray,
-    transform::Transform,
+    transform::{Transform, Transformable},
 };
+use uuid::Uuid;
+
+/// Tally of the rays cast into a world and of the ray-object tests they
+/// needed, filled in by `World::color_at_counted`.
+#[derive(Debug, Default)]
+pub struct RayCounter {
+    primary: Cell<usize>,
+    rays: Cell<usize>,
+    tests: Cell<usize>,
+}
+
+impl RayCounter {
+    pub fn primary_rays(&self) -> usize {
+        self.primary.get()
+    }
+
+    /// Every ray cast, including reflection, refraction and shadow rays.
+    pub fn total_rays(&self) -> usize {
+        self.rays.get()
+    }
+
+    pub fn intersections_tested(&self) -> usize {
+        self.tests.get()
+    }
+
+    // Camera rays are counted by the camera, which knows how many rays each
+    // pixel needs.
+    pub(crate) fn count_primary(&self) {
+        self.primary.set(self.primary.get() + 1);
+    }
+
+    fn count_ray(&self, objects_tested: usize) {
+        self.rays.set(self.rays.get() + 1);
+        self.tests.set(self.tests.get() + objects_tested);
+    }
+}
+
+/// All intersections of `ray` with `objects`, sorted by `t`. This is
+/// `World::intersect` without the world's settings.
+pub fn intersect_objects(objects: &[Object], ray: Ray) -> Intersections<'_> {
+    intersect_objects_counted(objects, ray, usize::MAX, &RayCounter::default())
+}
+
+// Keeps at most `max` intersections in front of the ray origin while
+// merging, so a ray through a huge stack of objects never collects them all.
+fn intersect_objects_counted<'a>(
+    objects: &'a [Object],
+    ray: Ray,
+    max: usize,
+    counter: &RayCounter,
+) -> Intersections<'a> {
+    let mut tested = 0;
+    let xs = (objects.iter())
+        .filter(|o| o.may_intersect(ray))
+        .inspect(|_| tested += 1)
+        .fold(Intersections(vec![]), |xs, o| {
+            let mut xs = xs.merge(o.intersect(ray));
+            xs.truncate_ahead(max);
+            xs
+        });
+    counter.count_ray(tested);
+    xs
+}
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct World {
     pub objects: Vec<Object>,
     pub lights: Vec<PointLight>,
+    pub background: Background,
+    /// Image-based light surrounding the scene, added to the ambient term.
+    pub environment: Option<EnvironmentLight>,
+    /// When the world has no lights, shade surfaces with their plain
+    /// material color instead of rendering them black.
+    pub unlit_fallback: bool,
+    /// Merge coincident intersections of the same object, e.g. when the
+    /// same geometry has been added to the world twice.
+    pub dedup_intersections: bool,
+    /// Scale down the local shading of materials whose diffuse, reflective
+    /// and transparency terms add up to more than 1.
+    pub energy_conservation: bool,
+    /// How far shadow, reflection and refraction rays start from the
+    /// surface. Large scenes need more than the default to avoid acne.
+    pub shadow_bias: f64,
+    /// Hits further than this along a ray are ignored, as if the ray had
+    /// missed everything.
+    pub max_distance: f64,
+    /// Soft cap on the intersections kept per ray in front of its origin.
+    /// Only the nearest ones are kept once it is exceeded; those behind the
+    /// origin are always kept.
+    pub max_intersections: usize,
+    /// Reflection and refraction rays whose accumulated weight along the
+    /// path drops below this aren't traced, as they'd barely contribute.
+    pub min_throughput: f64,
+}
+
+/// Differences between two worlds. Objects are matched by uuid; lights
+/// have no identity and are matched by index.
+#[derive(Debug, Default, PartialEq)]
+pub struct WorldDiff {
+    pub added_objects: Vec<Uuid>,
+    pub removed_objects: Vec<Uuid>,
+    pub changed_objects: Vec<Uuid>,
+    pub added_lights: Vec<usize>,
+    pub removed_lights: Vec<usize>,
+    pub changed_lights: Vec<usize>,
+}
+
+impl WorldDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == WorldDiff::default()
+    }
+}
+
+/// What rays that miss every object see.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    Solid(Color),
+    /// Blends from `bottom` for rays pointing straight down to `top` for
+    /// rays pointing straight up.
+    Gradient {
+        top: Color,
+        bottom: Color,
+    },
+}
+
+impl From<Color> for Background {
+    fn from(color: Color) -> Self {
+        Background::Solid(color)
+    }
+}
+
+impl Background {
+    pub fn gradient(top: Color, bottom: Color) -> Self {
+        Background::Gradient { top, bottom }
+    }
+
+    pub fn color_for(&self, direction: Vector) -> Color {
+        match *self {
+            Background::Solid(c) => c,
+            Background::Gradient { top, bottom } => {
+                let t = (direction.normalize().1 + 1.) / 2.;
+                bottom + (top - bottom) * t
+            }
+        }
+    }
+}
+
+/// What `World::pick` found along a ray.
+#[derive(Debug, Clone, Copy)]
+pub struct HitInfo<'a> {
+    pub object: &'a Object,
+    pub t: f64,
+    pub point: Point,
+    /// World-space normal, facing the ray.
+    pub normal: Vector,
+    /// Coordinates from the object's `uv_map`.
+    pub uv: (f64, f64),
+}
+
+/// A problem found by `World::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldError {
+    SingularTransform {
+        object: Uuid,
+    },
+    NonFiniteColor {
+        object: Uuid,
+    },
+    MaterialOutOfRange {
+        object: Uuid,
+        field: &'static str,
+        value: f64,
+    },
+    NonFiniteLight {
+        light: usize,
+    },
+    NoLights,
+}
+
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorldError::SingularTransform { object } => {
+                write!(f, "object {object} has a non-invertible transform")
+            }
+            WorldError::NonFiniteColor { object } => {
+                write!(f, "object {object} has a non-finite color")
+            }
+            WorldError::MaterialOutOfRange {
+                object,
+                field,
+                value,
+            } => write!(f, "object {object} has {field} out of range: {value}"),
+            WorldError::NonFiniteLight { light } => {
+                write!(f, "light {light} has a non-finite position or intensity")
+            }
+            WorldError::NoLights => write!(f, "the world has no lights"),
+        }
+    }
 }
 
+impl std::error::Error for WorldError {}
+
 impl Default for World {
     fn default() -> Self {
-        let lights = vec![PointLight {
-            intensity: Color::white(),
-            position: Point(-10., 10., -10.),
-        }];
+        let lights = vec![PointLight::new(Point(-10., 10., -10.), Color::white())];
         let objects = vec![
-            Object {
-                material: Material {
-                    color: Color(0.8, 1.0, 0.6),
-                    diffuse: 0.7,
-                    specular: 0.2,
-                    ..Material::default()
-                },
-                ..Object::sphere()
-            },
-            Object {
-                transform: Transform::scaling(0.5, 0.5, 0.5),
-                ..Object::sphere()
-            },
+            Object::sphere().set_material(Material {
+                color: Color(0.8, 1.0, 0.6),
+                diffuse: 0.7,
+                specular: 0.2,
+                ..Material::default()
+            }),
+            Object::sphere().set_transform(Transform::scaling(0.5, 0.5, 0.5)),
         ];
-        Self { objects, lights }
+        Self {
+            objects,
+            lights,
+            ..World::empty()
+        }
+    }
+}
+
+/// Builds a world one object or light at a time, starting from
+/// `World::empty`.
+#[derive(Debug)]
+pub struct WorldBuilder {
+    world: World,
+}
+
+impl WorldBuilder {
+    pub fn object(mut self, o: Object) -> Self {
+        self.world.add_object(o);
+        self
+    }
+
+    pub fn light(mut self, l: PointLight) -> Self {
+        self.world.add_light(l);
+        self
+    }
+
+    pub fn background(mut self, background: impl Into<Background>) -> Self {
+        self.world.background = background.into();
+        self
+    }
+
+    pub fn build(self) -> World {
+        self.world
     }
 }
 
 impl World {
+    pub fn builder() -> WorldBuilder {
+        WorldBuilder {
+            world: World::empty(),
+        }
+    }
+
     pub fn empty() -> Self {
         Self {
             objects: vec![],
             lights: vec![],
+            background: Background::Solid(Color::black()),
+            environment: None,
+            unlit_fallback: false,
+            dedup_intersections: false,
+            energy_conservation: false,
+            shadow_bias: EPSILON,
+            max_distance: f64::INFINITY,
+            max_intersections: usize::MAX,
+            min_throughput: 1e-3,
         }
     }
 
+    pub fn bounds(&self) -> BoundingBox {
+        (self.objects.iter()).fold(BoundingBox::empty(), |acc, o| acc.union(o.bounds()))
+    }
+
     pub fn intersect(&self, r: Ray) -> Intersections {
-        let mut xs = Intersections(vec![]);
-        (self.objects)
-            .iter()
-            .for_each(|o| xs.append(&mut o.intersect(r)));
-        xs.0.sort_unstable();
+        self.intersect_counted(r, &RayCounter::default())
+    }
+
+    fn intersect_counted(&self, r: Ray, counter: &RayCounter) -> Intersections<'_> {
+        let mut xs = intersect_objects_counted(&self.objects, r, self.max_intersections, counter);
+        if self.dedup_intersections {
+            xs.dedup();
+        }
         xs
     }
 
     pub fn shade_hit(&self, comps: &Computations, remaining: usize) -> Color {
-        (self.lights).iter().fold(Color::black(), |acc, light| {
-            let shadowed = self.is_shadowed(light.position, comps.over_point);
-            let surface = acc
-                + comps.object.material.lighting(
-                    &comps.object,
-                    *light,
-                    comps.over_point,
+        self.shade_hit_with(comps, remaining, 1., &RayCounter::default())
+    }
+
+    // `throughput` is the product of the reflective and transparency
+    // weights along the path that led to this hit.
+    fn shade_hit_with(
+        &self,
+        comps: &Computations,
+        remaining: usize,
+        throughput: f64,
+        counter: &RayCounter,
+    ) -> Color {
+        if self.lights.is_empty() && self.environment.is_none() && self.unlit_fallback {
+            return comps.object.material.color_at(comps.object, comps.point);
+        }
+        if comps.object.material.is_emissive_only() {
+            return comps.object.material.emission;
+        }
+        if let Some(opacity) = comps.object.material.shadow_catcher {
+            return self.shadow_catcher_color(comps, opacity, remaining, throughput, counter);
+        }
+        // Shadows are tested from the offset point to avoid acne, but the
+        // surface itself is shaded at the true hit point so patterns don't
+        // pick up the offset near their boundaries.
+        let shadows = self.shadow_factors_counted(comps.over_point, counter);
+        let material = if self.energy_conservation {
+            Cow::Owned(comps.object.material.clone().energy_conserving())
+        } else {
+            Cow::Borrowed(&comps.object.material)
+        };
+        // `normalv` always faces the eye; a one-sided surface seen from
+        // behind is lit with its true normal instead.
+        let normal = if comps.inside && !material.double_sided {
+            -comps.normalv
+        } else {
+            comps.normalv
+        };
+        let ambient = match &self.environment {
+            Some(env) => material.environment_lighting(comps.object, env, comps.point, normal),
+            None => Color::black(),
+        } + material.emission;
+        let surface = (self.lights)
+            .iter()
+            .zip(shadows)
+            .fold(ambient, |acc, (light, shadow)| {
+                let shadowed = shadow > 0.;
+                acc + material.lighting(
+                    comps.object,
+                    light,
+                    comps.point,
                     comps.eyev,
-                    comps.normalv,
+                    normal,
                     shadowed,
-                );
-            let reflected = self.reflected_color(comps, remaining);
-            let refracted = self.refracted_color(comps, remaining);
-            surface + reflected + refracted
-        })
+                )
+            });
+        // Reflection and refraction don't depend on the lights, so they're
+        // added once, even when there are none.
+        let reflected = self.reflected_color_with(comps, remaining, throughput, counter);
+        let refracted = self.refracted_color_with(comps, remaining, throughput, counter);
+        surface + reflected + refracted
     }
 
+    fn shadow_catcher_color(
+        &self,
+        comps: &Computations,
+        opacity: f64,
+        remaining: usize,
+        throughput: f64,
+        counter: &RayCounter,
+    ) -> Color {
+        if remaining == 0 {
+            return Color::black();
+        }
+        let behind = self.secondary_color_at(
+            Ray {
+                origin: comps.under_point,
+                direction: -comps.eyev,
+            },
+            remaining - 1,
+            throughput,
+            counter,
+        );
+        if self.lights.is_empty() {
+            return behind;
+        }
+        let shadows = self.shadow_factors_counted(comps.over_point, counter);
+        let shadowed = shadows.iter().sum::<f64>() / shadows.len() as f64;
+        behind * (1. - opacity * shadowed)
+    }
+
+    /// The color seen along a camera ray. Objects hidden from primary rays
+    /// are skipped.
     pub fn color_at(&self, r: Ray, remaining: usize) -> Color {
-        let xs = self.intersect(r);
-        if let Some((idx, hit)) = xs.hit() {
-            let comps = hit.prepare_computations(r, idx, &xs);
-            self.shade_hit(&comps, remaining)
+        self.color_at_counted(r, remaining, &RayCounter::default())
+    }
+
+    /// `color_at`, adding every ray it casts to `counter`.
+    pub fn color_at_counted(&self, r: Ray, remaining: usize, counter: &RayCounter) -> Color {
+        self.trace(r, remaining, true, 1., counter)
+    }
+
+    // Reflection and refraction rays skip objects hidden from secondary
+    // rays instead.
+    fn secondary_color_at(
+        &self,
+        r: Ray,
+        remaining: usize,
+        throughput: f64,
+        counter: &RayCounter,
+    ) -> Color {
+        self.trace(r, remaining, false, throughput, counter)
+    }
+
+    fn trace(
+        &self,
+        r: Ray,
+        remaining: usize,
+        primary: bool,
+        throughput: f64,
+        counter: &RayCounter,
+    ) -> Color {
+        let (xs, hit) = self.first_hit(r, primary, counter);
+        if let Some(idx) = hit {
+            let comps = xs[idx].prepare_computations_with_bias(r, idx, &xs, self.shadow_bias);
+            self.shade_hit_with(&comps, remaining, throughput, counter)
         } else {
-            Color::black()
+            self.background.color_for(r.direction)
+        }
+    }
+
+    /// The intersections of `r` with the objects it can see, and the index
+    /// of the hit among them unless it lies beyond `max_distance`. Camera
+    /// rays are `primary`, reflection and refraction rays are not.
+    pub(crate) fn first_hit(
+        &self,
+        r: Ray,
+        primary: bool,
+        counter: &RayCounter,
+    ) -> (Intersections<'_>, Option<usize>) {
+        let mut xs = self.intersect_counted(r, counter);
+        xs.0.retain(|i| {
+            if primary {
+                i.object.visible_primary
+            } else {
+                i.object.visible_secondary
+            }
+        });
+        let hit = (xs.hit())
+            .filter(|(_, h)| h.t <= self.max_distance)
+            .map(|(idx, _)| idx);
+        (xs, hit)
+    }
+
+    /// The first surface a camera ray sees, for editor-style picking.
+    pub fn pick(&self, r: Ray) -> Option<HitInfo<'_>> {
+        self.pick_counted(r, &RayCounter::default())
+    }
+
+    pub(crate) fn pick_counted(&self, r: Ray, counter: &RayCounter) -> Option<HitInfo<'_>> {
+        let (xs, idx) = self.first_hit(r, true, counter);
+        let idx = idx?;
+        // Copied out: `xs` is dropped before the result is returned.
+        let hit = xs[idx];
+        let comps = hit.prepare_computations(r, idx, &xs);
+        let local = comps.point.transform(hit.object.transform().inverse());
+        Some(HitInfo {
+            object: hit.object,
+            t: comps.t,
+            point: comps.point,
+            normal: comps.normalv,
+            uv: hit.object.uv_map.map(local),
+        })
+    }
+
+    pub fn colors_for_rays(&self, rays: &[Ray], depth: usize) -> Vec<Color> {
+        rays.iter().map(|&r| self.color_at(r, depth)).collect()
+    }
+
+    /// Follows `ray` through up to `max_bounces` mirror reflections and
+    /// returns, for each surface hit, the distance travelled along that
+    /// segment and the surface's own contribution to the final color.
+    /// Refraction is not followed, and the path ends at a rough surface,
+    /// whose blurred reflection has no single ray to follow.
+    pub fn trace_path(&self, ray: Ray, max_bounces: usize) -> Vec<(f64, Color)> {
+        let counter = RayCounter::default();
+        let mut path = vec![];
+        let mut ray = ray;
+        let mut weight = 1.;
+        for bounce in 0..=max_bounces {
+            let (xs, hit) = self.first_hit(ray, bounce == 0, &counter);
+            let Some(idx) = hit else {
+                break;
+            };
+            let comps = xs[idx].prepare_computations_with_bias(ray, idx, &xs, self.shadow_bias);
+            path.push((comps.t, self.shade_hit(&comps, 0) * weight));
+            let material = &comps.object.material;
+            weight *= material.reflective_at(comps.object, comps.point);
+            // The same cut-off `color_at` applies to reflection rays.
+            if weight == 0. || weight < self.min_throughput || material.roughness > 0. {
+                break;
+            }
+            ray = comps.reflected_ray();
         }
+        path
     }
 
     pub fn is_shadowed(&self, source: Point, point: Point) -> bool {
-        let v = source - point;
-        let distance = v.magnitude();
-        let direction = v.normalize();
+        let distance = point.distance(source);
+        let direction = (source - point).normalize();
         let r = Ray {
             origin: point,
             direction,
         };
-        let intersections = self.intersect(r);
-        let h = intersections.hit();
-        h.is_some() && h.unwrap().1.t < distance
+        self.occludes(r, distance)
+    }
+
+    /// Whether any shadow-casting object blocks `ray` before `max_t`.
+    /// Stops at the first blocker found.
+    pub fn occludes(&self, ray: Ray, max_t: f64) -> bool {
+        self.objects.iter().any(|o| o.occludes(ray, max_t))
+    }
+
+    pub fn shadow_factors(&self, point: Point) -> Vec<f64> {
+        self.shadow_factors_counted(point, &RayCounter::default())
+    }
+
+    fn shadow_factors_counted(&self, point: Point, counter: &RayCounter) -> Vec<f64> {
+        let mut factors: Vec<Option<f64>> = vec![None; self.lights.len()];
+        for i in 0..self.lights.len() {
+            if factors[i].is_some() {
+                continue;
+            }
+            let direction = (self.lights[i].position - point).normalize();
+            let r = Ray {
+                origin: point,
+                direction,
+            };
+            let intersections = self.intersect_counted(r, counter);
+            let hit = (intersections.0.iter())
+                .find(|x| x.t >= 0. && x.object.casts_shadow && x.object.visible_secondary)
+                .map(|x| x.t);
+            // Lights lying along the same shadow ray share its intersections.
+            for (factor, light) in factors.iter_mut().zip(&self.lights).skip(i) {
+                let v = light.position - point;
+                if factor.is_none() && v.normalize().almost_eq(direction, EPSILON) {
+                    let distance = point.distance(light.position);
+                    *factor = Some(match hit {
+                        Some(t) if t < distance => 1.,
+                        _ => 0.,
+                    });
+                }
+            }
+        }
+        factors.into_iter().map(|f| f.unwrap_or(0.)).collect()
     }
 
     pub fn reflected_color(&self, comps: &Computations, remaining: usize) -> Color {
-        if comps.object.material.reflective == 0. || remaining == 0 {
+        self.reflected_color_with(comps, remaining, 1., &RayCounter::default())
+    }
+
+    fn reflected_color_with(
+        &self,
+        comps: &Computations,
+        remaining: usize,
+        throughput: f64,
+        counter: &RayCounter,
+    ) -> Color {
+        let material = &comps.object.material;
+        let reflective = material.reflective_at(comps.object, comps.point);
+        if reflective == 0. || remaining == 0 {
             Color::black()
+        } else if material.roughness == 0. {
+            let color = self.weighted(throughput, reflective, |throughput| {
+                self.secondary_color_at(comps.reflected_ray(), remaining - 1, throughput, counter)
+            });
+
+            color * reflective
         } else {
-            let reflect_ray = Ray {
-                origin: comps.over_point,
-                direction: comps.reflectv,
+            let directions = glossy_directions(comps.reflectv, material.roughness);
+            let sample = |throughput: f64, acc: Color, &d: &Vector| {
+                // Samples dipping below the surface fall back to the mirror
+                // direction.
+                let direction = if d.dot(comps.normalv) > 0. {
+                    d
+                } else {
+                    comps.reflectv
+                };
+                let ray = Ray {
+                    origin: comps.spawn_point(direction),
+                    direction,
+                };
+                acc + self.secondary_color_at(ray, remaining - 1, throughput, counter)
             };
-            let color = self.color_at(reflect_ray, remaining - 1);
-
-            color * comps.object.material.reflective
+            let total = self.weighted(throughput, reflective, |throughput| {
+                (directions.iter()).fold(Color::black(), |acc, d| sample(throughput, acc, d))
+            });
+            total * (reflective / directions.len() as f64)
         }
     }
 
     pub fn refracted_color(&self, comps: &Computations, remaining: usize) -> Color {
+        self.refracted_color_with(comps, remaining, 1., &RayCounter::default())
+    }
+
+    fn refracted_color_with(
+        &self,
+        comps: &Computations,
+        remaining: usize,
+        throughput: f64,
+        counter: &RayCounter,
+    ) -> Color {
         if comps.object.material.transparency == 0. || remaining == 0 {
             Color::black()
         } else {
-            let n_ratio = comps.n1 / comps.n2;
-            let cos_i = comps.eyev.dot(comps.normalv);
-            let sin2_t = n_ratio.powi(2) * (1. - cos_i.powi(2));
-            if sin2_t > 1. {
-                Color::black()
-            } else {
-                let cos_t = (1. - sin2_t).sqrt();
+            let transparency = comps.object.material.transparency;
+            // Under total internal reflection the light that would have
+            // been transmitted is mirrored instead.
+            let ray = comps.refracted_ray().unwrap_or(comps.reflected_ray());
+            let color = self.weighted(throughput, transparency, |throughput| {
+                self.secondary_color_at(ray, remaining - 1, throughput, counter)
+            });
+            color * transparency
+        }
+    }
 
-                let direction = comps.normalv * (n_ratio * cos_i - cos_t) - comps.eyev * n_ratio;
+    // Runs `trace` with `throughput` scaled by `weight`, or returns black
+    // without tracing when that drops below `min_throughput`.
+    fn weighted(&self, throughput: f64, weight: f64, trace: impl FnOnce(f64) -> Color) -> Color {
+        let throughput = throughput * weight;
+        if throughput < self.min_throughput {
+            return Color::black();
+        }
+        trace(throughput)
+    }
 
-                let refract_ray = Ray {
-                    origin: comps.under_point,
-                    direction,
-                };
+    /// Checks for states that render wrongly or panic, and reports all of
+    /// them rather than stopping at the first.
+    pub fn validate(&self) -> Result<(), Vec<WorldError>> {
+        let mut errors = vec![];
+        for o in &self.objects {
+            let object = o.uuid;
+            if !o.transform().is_invertible() {
+                errors.push(WorldError::SingularTransform { object });
+            }
+            let m = &o.material;
+            if !m.color.is_finite() {
+                errors.push(WorldError::NonFiniteColor { object });
+            }
+            let unit = |x: f64| (0. ..=1.).contains(&x);
+            let positive = |x: f64| x.is_finite() && x > 0.;
+            let checks = [
+                ("ambient", m.ambient, unit(m.ambient)),
+                ("diffuse", m.diffuse, unit(m.diffuse)),
+                ("specular", m.specular, unit(m.specular)),
+                ("reflective", m.reflective, unit(m.reflective)),
+                ("roughness", m.roughness, unit(m.roughness)),
+                ("transparency", m.transparency, unit(m.transparency)),
+                ("shininess", m.shininess, positive(m.shininess)),
+                (
+                    "refractive_index",
+                    m.refractive_index,
+                    positive(m.refractive_index),
+                ),
+            ];
+            for (field, value, ok) in checks {
+                if !ok {
+                    errors.push(WorldError::MaterialOutOfRange {
+                        object,
+                        field,
+                        value,
+                    });
+                }
+            }
+        }
+        for (i, l) in self.lights.iter().enumerate() {
+            let Point(x, y, z) = l.position;
+            if !(l.intensity.is_finite() && x.is_finite() && y.is_finite() && z.is_finite()) {
+                errors.push(WorldError::NonFiniteLight { light: i });
+            }
+        }
+        if self.lights.is_empty() && self.environment.is_none() && !self.unlit_fallback {
+            errors.push(WorldError::NoLights);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 
-                self.color_at(refract_ray, remaining - 1) * comps.object.material.transparency
+    /// Whether everything but the objects and lights is the same.
+    pub(crate) fn same_settings(&self, other: &World) -> bool {
+        // Spelled out so a new setting can't be forgotten here.
+        let World {
+            objects: _,
+            lights: _,
+            background,
+            environment,
+            unlit_fallback,
+            dedup_intersections,
+            energy_conservation,
+            shadow_bias,
+            max_distance,
+            max_intersections,
+            min_throughput,
+        } = self;
+        *background == other.background
+            && *environment == other.environment
+            && *unlit_fallback == other.unlit_fallback
+            && *dedup_intersections == other.dedup_intersections
+            && *energy_conservation == other.energy_conservation
+            && *shadow_bias == other.shadow_bias
+            && *max_distance == other.max_distance
+            && *max_intersections == other.max_intersections
+            && *min_throughput == other.min_throughput
+    }
+
+    /// What changed going from `self` to `other`.
+    pub fn diff(&self, other: &World) -> WorldDiff {
+        let mut diff = WorldDiff::default();
+        for o in &self.objects {
+            match other.objects.iter().find(|p| p.uuid == o.uuid) {
+                None => diff.removed_objects.push(o.uuid),
+                Some(p) if p != o => diff.changed_objects.push(o.uuid),
+                Some(_) => {}
             }
         }
+        for o in &other.objects {
+            if !self.objects.iter().any(|p| p.uuid == o.uuid) {
+                diff.added_objects.push(o.uuid);
+            }
+        }
+        let (before, after) = (self.lights.len(), other.lights.len());
+        diff.removed_lights = (after..before).collect();
+        diff.added_lights = (before..after).collect();
+        diff.changed_lights = (0..before.min(after))
+            .filter(|&i| self.lights[i] != other.lights[i])
+            .collect();
+        diff
     }
 
     pub fn add_object(&mut self, o: Object) {
         self.objects.push(o);
     }
 
+    /// Every leaf object in the world. There are no groups yet, so this is
+    /// just the top-level objects.
+    pub fn primitives(&self) -> impl Iterator<Item = &Object> {
+        self.objects.iter()
+    }
+
+    /// The first object labelled `name`, if any.
+    pub fn find_by_name(&self, name: &str) -> Option<&Object> {
+        self.primitives().find(|o| o.name.as_deref() == Some(name))
+    }
+
     pub fn add_light(&mut self, l: PointLight) {
         self.lights.push(l);
     }
+
+    pub fn add_headlight(&mut self, camera: &Camera, intensity: Color) {
+        let position = Point(0., 0., 0.).transform(camera.transform.inverse());
+        self.add_light(PointLight::new(position, intensity));
+    }
+}
+
+const GLOSSY_SAMPLES: usize = 16;
+
+// Directions spread evenly over a cone around `axis`, on a golden-angle
+// spiral so the result is deterministic. Roughness 1 opens the cone to a
+// full hemisphere.
+fn glossy_directions(axis: Vector, roughness: f64) -> Vec<Vector> {
+    let helper = if axis.0.abs() < 0.9 {
+        Vector(1., 0., 0.)
+    } else {
+        Vector(0., 1., 0.)
+    };
+    let u = axis.cross(helper).normalize();
+    let v = axis.cross(u);
+    let cos_max = (roughness * PI / 2.).cos();
+    let golden_angle = PI * (3. - 5f64.sqrt());
+    (0..GLOSSY_SAMPLES)
+        .map(|i| {
+            let cos_theta = 1. - (i as f64 + 0.5) / GLOSSY_SAMPLES as f64 * (1. - cos_max);
+            let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+            let phi = i as f64 * golden_angle;
+            u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + axis * cos_theta
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        f64::consts::PI,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
 
     use crate::{
         assert_almost_eq,
-        canvas::Color,
+        camera::Camera,
+        canvas::{Canvas, Color},
         geometry::{Point, Vector},
         intersection::{Intersection, Intersections},
-        light::PointLight,
-        macros::AlmostEq,
+        light::{EnvironmentLight, PointLight},
+        macros::{AlmostEq, EPSILON},
         object::Object,
         pattern::Pattern,
         ray::Ray,
+        shape::{Primitive, Sphere},
+        texture::ImageTexture,
         transform::Transform,
     };
 
-    use super::World;
-    fn almost_eq(c1: Color, c2: Color) -> bool {
-        (c1.0 - c2.0).abs() < 1e6 && (c1.1 - c2.1).abs() < 1e6 && (c1.2 - c2.2).abs() < 1e6
-    }
+    use super::{intersect_objects, Background, RayCounter, World, WorldError};
 
     #[test]
     fn intersect_a_world_with_a_ray() {
@@ -183,25 +841,58 @@ mod tests {
     fn shading_an_intersection() {
         let w = World::default();
         let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
-        let s = w.objects[0];
-        let i = Intersection { t: 4., object: &s };
+        let s = &w.objects[0];
+        let i = Intersection { t: 4., object: s };
         let comps = i.prepare_computations(r, 0, &Intersections(vec![i]));
         let c = w.shade_hit(&comps, 5);
-        assert!(almost_eq(c, Color(0.38066, 0.47583, 0.2855)));
+        assert_almost_eq!(c, Color(0.38066, 0.47583, 0.2855));
     }
     #[test]
     fn shading_an_intersection_from_the_inside() {
         let mut w = World::default();
-        w.lights[0] = PointLight {
-            position: Point(0., 0.25, 0.),
-            intensity: Color::white(),
-        };
+        w.lights[0] = PointLight::new(Point(0., 0.25, 0.), Color::white());
         let r = Ray::new(Point(0., 0., 0.), Vector(0., 0., 1.));
-        let s = w.objects[1];
-        let i = Intersection { t: 0.5, object: &s };
+        let s = &w.objects[1];
+        let i = Intersection { t: 0.5, object: s };
         let comps = i.prepare_computations(r, 0, &Intersections(vec![i]));
         let c = w.shade_hit(&comps, 5);
-        assert!(almost_eq(c, Color(0.90498, 0.90498, 0.90498)));
+        assert_almost_eq!(c, Color(0.90498, 0.90498, 0.90498));
+    }
+    #[test]
+    fn an_emissive_surface_shades_to_its_emission() {
+        let lamp = Object::sphere()
+            .set_ambient(0.)
+            .set_diffuse(0.)
+            .set_specular(0.)
+            .set_emission(Color(4., 3., 2.));
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        let i = Intersection {
+            t: 4.,
+            object: &lamp,
+        };
+        let comps = i.prepare_computations(r, 0, &Intersections(vec![i]));
+        let mut w = World::default();
+        assert_eq!(w.shade_hit(&comps, 5), Color(4., 3., 2.));
+        w.lights = vec![PointLight::new(Point(0., 0., -10.), Color(0.5, 0., 1.))];
+        assert_eq!(w.shade_hit(&comps, 5), Color(4., 3., 2.));
+    }
+    #[test]
+    fn one_sided_surfaces_are_dark_from_behind() {
+        let mut w = World::empty();
+        w.add_light(PointLight::new(Point(0., -5., 0.), Color::white()));
+        let r = Ray::new(Point(0., -2., 0.), Vector(0., 1., 0.));
+        let shade = |plane: &Object| {
+            let i = Intersection {
+                t: 2.,
+                object: plane,
+            };
+            let comps = i.prepare_computations(r, 0, &Intersections(vec![i]));
+            w.shade_hit(&comps, 5)
+        };
+        let two_sided = shade(&Object::plane());
+        assert_almost_eq!(two_sided, Color(1.9, 1.9, 1.9));
+        let one_sided = shade(&Object::plane().set_double_sided(false));
+        assert_almost_eq!(one_sided, Color(0.1, 0.1, 0.1));
     }
     #[test]
     fn the_color_when_a_ray_misses() {
@@ -213,7 +904,7 @@ mod tests {
     fn the_color_when_a_ray_hits() {
         let w = World::default();
         let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
-        assert!(almost_eq(w.color_at(r, 5), Color(0.38066, 0.47583, 0.2855)));
+        assert_almost_eq!(w.color_at(r, 5), Color(0.38066, 0.47583, 0.2855));
     }
     #[test]
     fn the_color_with_an_intersection_behind_the_ray() {
@@ -249,17 +940,391 @@ mod tests {
         assert!(!w.is_shadowed(w.lights[0].position, p));
     }
     #[test]
-    fn shade_hit_is_given_an_intersection_in_shadow() {
-        let light = PointLight {
-            position: Point(0., 0., -10.),
-            intensity: Color::white(),
+    fn shadow_factors_match_individual_shadow_tests() {
+        let mut w = World::default();
+        w.add_light(PointLight::new(Point(10., 10., 10.), Color(0.5, 0.5, 0.5)));
+        w.add_light(PointLight::new(
+            Point(-20., 20., -20.),
+            Color(0.2, 0.2, 0.2),
+        ));
+        for p in [
+            Point(0., 10., 0.),
+            Point(10., -10., 10.),
+            Point(-10., -10., -10.),
+            Point(-2., 2., -2.),
+        ] {
+            let factors = w.shadow_factors(p);
+            assert_eq!(factors.len(), w.lights.len());
+            for (light, factor) in w.lights.iter().zip(factors) {
+                let expected = if w.is_shadowed(light.position, p) {
+                    1.
+                } else {
+                    0.
+                };
+                assert_eq!(factor, expected);
+            }
+        }
+        // A blocker closer than EPSILON counts for both.
+        let w = World {
+            objects: vec![Object::plane()],
+            lights: vec![PointLight::new(Point(0., 10., 0.), Color::white())],
+            ..World::empty()
+        };
+        let p = Point(0., -EPSILON / 2., 0.);
+        assert!(w.is_shadowed(w.lights[0].position, p));
+        assert_eq!(w.shadow_factors(p), vec![1.]);
+    }
+    #[test]
+    fn shadows_from_non_uniformly_scaled_occluders() {
+        let point = Point(0., 0., 0.);
+        let light = Point(0., 0., 5.);
+        let shadowed_by = |occluder: Object| {
+            let w = World {
+                objects: vec![occluder],
+                lights: vec![PointLight::new(light, Color::white())],
+                ..World::empty()
+            };
+            assert_eq!(w.shadow_factors(point)[0] > 0., w.is_shadowed(light, point));
+            w.is_shadowed(light, point)
+        };
+        // A thin slab between the point and the light, whose local hit is
+        // ten times further along a normalized local ray.
+        let slab = Transform::translation(0., 0., 2.5) * Transform::scaling(1., 1., 0.1);
+        assert!(shadowed_by(Object::sphere().set_transform(slab)));
+        // A long occluder entirely behind the light, whose local hit would
+        // be ten times closer.
+        let pole = Transform::translation(0., 0., 20.) * Transform::scaling(1., 1., 10.);
+        assert!(!shadowed_by(Object::sphere().set_transform(pole)));
+    }
+    #[test]
+    fn occludes_agrees_with_the_full_intersection_test() {
+        let w = World::default();
+        let light = w.lights[0].position;
+        for p in [
+            Point(0., 10., 0.),
+            Point(10., -10., 10.),
+            Point(-20., 20., -20.),
+            Point(-2., 2., -2.),
+        ] {
+            let distance = p.distance(light);
+            let r = Ray::new(p, (light - p).normalize());
+            let full = matches!(w.intersect(r).hit(), Some((_, h)) if h.t < distance);
+            assert_eq!(w.occludes(r, distance), full);
+        }
+    }
+
+    #[test]
+    fn an_object_hidden_from_the_camera_still_casts_a_shadow() {
+        let mut w = World::empty();
+        w.add_light(PointLight::new(Point(0., 10., 0.), Color::white()));
+        w.add_object(Object::plane());
+        w.add_object(
+            Object::sphere()
+                .set_color(Color::red())
+                .set_transform(Transform::translation(0., 2., 0.))
+                .set_visible_primary(false),
+        );
+        let r = Ray::new(Point(0., 5., 0.), Vector(0., -1., 0.));
+        // Seen straight through, the sphere leaves the floor in its shadow.
+        assert_eq!(w.color_at(r, 5), Color(0.1, 0.1, 0.1));
+        w.objects[1].set_visible_primary(true);
+        let sphere = w.color_at(r, 5);
+        assert!(sphere.0 > sphere.1);
+        w.objects[1].set_visible_primary(false);
+        w.objects[1].set_visible_secondary(false);
+        assert_eq!(w.color_at(r, 5), Color(1.9, 1.9, 1.9));
+    }
+
+    #[test]
+    fn missed_rays_see_the_background_gradient() {
+        let mut w = World::empty();
+        w.background = Background::gradient(Color(0.2, 0.4, 1.), Color::white());
+        let sky = |direction| w.color_at(Ray::new(Point(0., 0., 0.), direction), 5);
+        assert_almost_eq!(sky(Vector(0., 1., 0.)), Color(0.2, 0.4, 1.));
+        assert_eq!(sky(Vector(0., -3., 0.)), Color::white());
+        assert_almost_eq!(sky(Vector(1., 0., 1.)), Color(0.6, 0.7, 1.));
+    }
+
+    #[test]
+    fn finding_objects_by_name() {
+        let mut w = World::default();
+        let moon = Object::sphere()
+            .set_transform(Transform::translation(0., 5., 0.))
+            .set_name("moon");
+        w.add_object(moon.clone());
+        assert_eq!(w.find_by_name("moon"), Some(&moon));
+        assert_eq!(w.find_by_name("sun"), None);
+        assert_eq!(w.objects[0].name, None);
+    }
+
+    #[test]
+    fn primitives_of_a_flat_world_are_its_objects() {
+        let w = World::default();
+        let primitives: Vec<&Object> = w.primitives().collect();
+        assert_eq!(primitives, vec![&w.objects[0], &w.objects[1]]);
+        assert_eq!(World::empty().primitives().count(), 0);
+    }
+    #[test]
+    fn rays_missing_the_bounding_sphere_skip_the_exact_test() {
+        let w = World {
+            objects: vec![Object::sphere().set_transform(Transform::translation(5., 0., 0.))],
+            ..World::empty()
+        };
+        let (center, radius) = w.objects[0].bounding_sphere();
+        assert_almost_eq!(center, Point(5., 0., 0.));
+        assert_almost_eq!(radius, 3f64.sqrt());
+        // (hits, rays, objects tested) for a single ray.
+        let counts = |origin: Point| {
+            let counter = RayCounter::default();
+            let xs = w.intersect_counted(Ray::new(origin, Vector(0., 0., 1.)), &counter);
+            (
+                xs.0.len(),
+                counter.total_rays(),
+                counter.intersections_tested(),
+            )
+        };
+        assert_eq!(counts(Point(0., 0., -5.)), (0, 1, 0));
+        // Inside the bounding sphere but outside the sphere itself.
+        assert_eq!(counts(Point(6.2, 1.2, -5.)), (0, 1, 1));
+        assert_eq!(counts(Point(5., 0., -5.)), (2, 1, 1));
+    }
+    #[test]
+    fn intersecting_a_plain_list_of_objects() {
+        let objects = World::default().objects;
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        let xs = intersect_objects(&objects, r);
+        let w = World {
+            objects: objects.clone(),
+            ..World::empty()
+        };
+        assert_eq!(xs.0, w.intersect(r).0);
+        let ts: Vec<f64> = xs.0.iter().map(|i| i.t).collect();
+        assert_eq!(ts, vec![4., 4.5, 5.5, 6.]);
+    }
+
+    #[test]
+    fn building_a_world_fluently() {
+        let sphere = Object::sphere().set_color(Color::red());
+        let floor = Object::plane();
+        let light = PointLight::new(Point(-10., 10., -10.), Color::white());
+        let built = World::builder()
+            .object(sphere.clone())
+            .light(light.clone())
+            .object(floor.clone())
+            .background(Color(0.1, 0.2, 0.3))
+            .build();
+        let literal = World {
+            objects: vec![sphere, floor],
+            lights: vec![light],
+            background: Background::Solid(Color(0.1, 0.2, 0.3)),
+            ..World::empty()
+        };
+        assert_eq!(built, literal);
+        let sky = Background::gradient(Color::white(), Color::blue());
+        assert_eq!(World::builder().background(sky).build().background, sky);
+    }
+
+    #[test]
+    fn picking_the_front_of_a_sphere() {
+        let mut w = World::empty();
+        w.add_object(Object::sphere());
+        let hit = w
+            .pick(Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.)))
+            .unwrap();
+        assert_eq!(hit.object.uuid, w.objects[0].uuid);
+        assert_eq!(hit.t, 4.);
+        assert_almost_eq!(hit.point, Point(0., 0., -1.));
+        assert_almost_eq!(hit.normal, Vector(0., 0., -1.));
+        assert_almost_eq!(hit.uv.0, 0.);
+        assert_almost_eq!(hit.uv.1, 0.5);
+        assert!(w
+            .pick(Ray::new(Point(0., 5., -5.), Vector(0., 0., 1.)))
+            .is_none());
+        w.max_distance = 3.;
+        assert!(w
+            .pick(Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.)))
+            .is_none());
+    }
+
+    #[test]
+    fn validating_a_world_reports_every_problem() {
+        let mut w = World::default();
+        assert_eq!(w.validate(), Ok(()));
+        w.objects[0].set_transform(Transform::scaling(0., 1., 1.));
+        w.objects[1].set_color(Color(f64::NAN, 0., 0.));
+        let errors = w.validate().unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                WorldError::SingularTransform {
+                    object: w.objects[0].uuid
+                },
+                WorldError::NonFiniteColor {
+                    object: w.objects[1].uuid
+                },
+            ]
+        );
+        assert_eq!(World::empty().validate(), Err(vec![WorldError::NoLights]));
+    }
+
+    #[test]
+    fn diffing_worlds_reports_added_changed_and_removed_items() {
+        let w = World::default();
+        let mut other = World {
+            objects: w.objects.clone(),
+            lights: w.lights.clone(),
+            ..World::empty()
         };
+        assert!(w.diff(&other).is_empty());
+
+        let extra = Object::sphere();
+        other.add_object(extra.clone());
+        let diff = w.diff(&other);
+        assert_eq!(diff.added_objects, vec![extra.uuid]);
+        assert!(diff.removed_objects.is_empty() && diff.changed_objects.is_empty());
+
+        other.objects[0].set_color(Color::white());
+        other.objects.remove(1);
+        other.lights[0].intensity = Color(0.5, 0.5, 0.5);
+        other.add_light(PointLight::new(Point(0., 10., 0.), Color::white()));
+        let diff = w.diff(&other);
+        assert_eq!(diff.changed_objects, vec![w.objects[0].uuid]);
+        assert_eq!(diff.removed_objects, vec![w.objects[1].uuid]);
+        assert_eq!(diff.changed_lights, vec![0]);
+        assert_eq!(diff.added_lights, vec![1]);
+    }
+
+    #[test]
+    fn surfaces_facing_a_bright_environment_are_lit_brighter() {
+        let mut sky = Canvas::new(4, 3, Some(Color::black()));
+        for x in 0..4 {
+            sky.write_pixel(x, 0, Color::white());
+        }
+        let texture = Arc::new(ImageTexture::new(sky));
+        let mut w = World::empty();
+        w.environment = Some(EnvironmentLight::new(texture));
+        w.add_object(Object::sphere().set_ambient(1.));
+        let top = w.color_at(Ray::new(Point(0., 5., 0.), Vector(0., -1., 0.)), 0);
+        let bottom = w.color_at(Ray::new(Point(0., -5., 0.), Vector(0., 1., 0.)), 0);
+        assert_eq!(top, Color::white());
+        assert_eq!(bottom, Color::black());
+    }
+    #[test]
+    fn a_mirror_lit_only_by_the_environment_still_reflects() {
+        let dark = Arc::new(ImageTexture::new(Canvas::new(4, 3, Some(Color::black()))));
+        let mut w = World::empty();
+        w.environment = Some(EnvironmentLight::new(dark));
+        w.background = Background::Solid(Color(0., 0., 1.));
+        w.add_object(Object::sphere().set_reflective(1.));
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        assert_almost_eq!(w.color_at(r, 5), Color(0., 0., 1.));
+    }
+
+    #[derive(Debug)]
+    struct CountingSphere;
+
+    static COUNTED_INTERSECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+    impl Primitive for CountingSphere {
+        fn local_intersect(&self, ray: Ray) -> Vec<f64> {
+            COUNTED_INTERSECTIONS.fetch_add(1, Ordering::Relaxed);
+            Sphere.local_intersect(ray)
+        }
+
+        fn local_normal_at(&self, point: Point) -> Vector {
+            Sphere.local_normal_at(point)
+        }
+    }
+
+    #[test]
+    fn occludes_stops_at_the_first_blocker() {
+        let mut w = World::empty();
+        w.add_object(Object::sphere());
+        w.add_object(
+            Object::custom(Arc::new(CountingSphere))
+                .set_transform(Transform::translation(0., 0., 3.)),
+        );
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        assert!(w.occludes(r, 10.));
+        assert_eq!(COUNTED_INTERSECTIONS.load(Ordering::Relaxed), 0);
+        assert!(!w.occludes(r, 3.));
+        assert_eq!(COUNTED_INTERSECTIONS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn objects_that_dont_cast_shadows_are_skipped() {
+        let mut w = World::default();
+        let p = Point(10., -10., 10.);
+        for o in w.objects.iter_mut() {
+            o.set_casts_shadow(false);
+        }
+        assert!(!w.is_shadowed(w.lights[0].position, p));
+        assert_eq!(w.shadow_factors(p), vec![0.]);
+    }
+    #[test]
+    fn a_headlight_is_placed_at_the_camera_origin() {
+        let mut w = World::empty();
+        let from = Point(1., 3., 2.);
+        let t = Transform::view_transform(from, Point(4., -2., 8.), Vector(1., 1., 0.));
+        let c = Camera::new(10, 10, PI / 2., Some(t));
+        w.add_headlight(&c, Color(0.5, 0.5, 0.5));
+        assert_eq!(w.lights.len(), 1);
+        assert_almost_eq!(w.lights[0].position, from);
+        assert_eq!(w.lights[0].intensity, Color(0.5, 0.5, 0.5));
+    }
+    #[test]
+    fn a_world_without_lights_renders_black_by_default() {
+        let mut w = World::empty();
+        w.add_object(Object::sphere().set_color(Color::red()));
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        assert_eq!(w.color_at(r, 5), Color::black());
+    }
+    #[test]
+    fn a_world_without_lights_shows_material_colors_with_the_unlit_fallback() {
+        let mut w = World::empty();
+        w.unlit_fallback = true;
+        w.add_object(Object::sphere().set_color(Color::red()));
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        assert_eq!(w.color_at(r, 5), Color::red());
+    }
+    #[test]
+    fn coincident_planes_produce_a_single_hit_after_deduplication() {
+        let mut w = World::empty();
+        let plane = Object::plane().set_transform(Transform::translation(0., -1., 0.));
+        w.add_object(plane.clone());
+        w.add_object(plane);
+        let r = Ray::new(Point(0., 0., 0.), Vector(0., -1., 0.));
+        assert_eq!(w.intersect(r).0.len(), 2);
+        w.dedup_intersections = true;
+        let xs = w.intersect(r);
+        assert_eq!(xs.0.len(), 1);
+        assert_eq!(xs.hit().unwrap().1.t, 1.);
+    }
+    #[test]
+    fn patterns_are_sampled_at_the_true_surface_point() {
+        let w = World::default();
+        let s = Object::sphere()
+            .set_pattern(Pattern::checkers_pattern(Color::white(), Color::black()))
+            .set_ambient(1.)
+            .set_diffuse(0.)
+            .set_specular(0.);
+        let r = Ray::new(Point(0., 0., 0.), Vector(1., 0., 0.));
+        let i = Intersection { t: 1., object: &s };
+        let comps = i.prepare_computations(r, 0, &Intersections(vec![i]));
+        assert_eq!(comps.point, Point(1., 0., 0.));
+        assert!(comps.over_point.0 < 1.);
+        assert_eq!(w.shade_hit(&comps, 5), Color::black());
+    }
+    #[test]
+    fn shade_hit_is_given_an_intersection_in_shadow() {
+        let light = PointLight::new(Point(0., 0., -10.), Color::white());
         let s1 = Object::sphere();
         let s2 = Object::sphere().set_transform(Transform::translation(0., 0., 10.));
 
         let w = World {
             lights: vec![light],
-            objects: vec![s1, s2],
+            objects: vec![s1, s2.clone()],
+            ..World::empty()
         };
         let r = Ray {
             origin: Point(0., 0., 5.),
@@ -277,7 +1342,7 @@ mod tests {
             origin: Point(0., 0., 0.),
             direction: Vector(0., 0., 1.),
         };
-        let mut s = w.objects[1];
+        let mut s = w.objects[1].clone();
         s.material.set_ambient(1.);
         let i = Intersection { t: 1., object: &s };
         let comps = i.prepare_computations(r, 0, &Intersections(vec![i]));
@@ -289,7 +1354,7 @@ mod tests {
         let shape = Object::plane()
             .set_reflective(0.5)
             .set_transform(Transform::translation(0., -1., 0.));
-        w.add_object(shape);
+        w.add_object(shape.clone());
         let r = Ray {
             origin: Point(0., 0., -3.),
             direction: Vector(0., -2f64.sqrt() / 2., 2f64.sqrt() / 2.),
@@ -305,12 +1370,61 @@ mod tests {
         );
     }
     #[test]
+    fn a_reflectance_pattern_limits_reflection_to_its_bright_stripes() {
+        let w = World::default();
+        let mirror = Object::plane()
+            .set_reflective(1.)
+            .set_transform(Transform::translation(0., -1., 0.));
+        let mut striped = mirror.clone();
+        striped.set_reflective_pattern(Pattern::stripe_pattern(Color::white(), Color::black()));
+        let reflected = |shape: &Object, x: f64| {
+            let r = Ray {
+                origin: Point(x, 0., -3.),
+                direction: Vector(0., -2f64.sqrt() / 2., 2f64.sqrt() / 2.),
+            };
+            let i = Intersection {
+                t: 2f64.sqrt(),
+                object: shape,
+            };
+            let comps = i.prepare_computations(r, 0, &Intersections(vec![i]));
+            w.reflected_color(&comps, 5)
+        };
+        assert_ne!(reflected(&mirror, 0.5), Color::black());
+        assert_eq!(reflected(&striped, 0.5), reflected(&mirror, 0.5));
+        assert_eq!(reflected(&striped, 1.5), Color::black());
+    }
+    #[test]
+    fn a_rough_surface_blurs_its_reflection() {
+        let w = World::default();
+        let r = Ray {
+            origin: Point(0., 0., -3.),
+            direction: Vector(0., -2f64.sqrt() / 2., 2f64.sqrt() / 2.),
+        };
+        let reflected = |roughness| {
+            let shape = Object::plane()
+                .set_reflective(0.5)
+                .set_roughness(roughness)
+                .set_transform(Transform::translation(0., -1., 0.));
+            let i = Intersection {
+                t: 2f64.sqrt(),
+                object: &shape,
+            };
+            let comps = i.prepare_computations(r, 0, &Intersections(vec![i]));
+            w.reflected_color(&comps, 5)
+        };
+        let sharp = reflected(0.);
+        assert_almost_eq!(sharp, Color(0.19032, 0.2379, 0.14274));
+        // Part of the cone misses the sphere the mirror direction hits.
+        let rough = reflected(0.5);
+        assert!(rough.0 < sharp.0 && rough.0 > 0.);
+    }
+    #[test]
     fn shade_hit_with_a_reflective_material() {
         let mut w = World::default();
         let shape = Object::plane()
             .set_reflective(0.5)
             .set_transform(Transform::translation(0., -1., 0.));
-        w.add_object(shape);
+        w.add_object(shape.clone());
         let r = Ray {
             origin: Point(0., 0., -3.),
             direction: Vector(0., -2f64.sqrt() / 2., 2f64.sqrt() / 2.),
@@ -323,13 +1437,180 @@ mod tests {
         assert_almost_eq!(w.shade_hit(&comps, 5), Color(0.87677, 0.92436, 0.82918));
     }
     #[test]
+    fn the_number_of_intersections_per_ray_can_be_capped() {
+        let mut w = World::empty();
+        w.add_light(PointLight::new(Point(-10., 10., -10.), Color::white()));
+        for i in 0..100 {
+            let glass = Object::glass_sphere().set_transform(Transform::translation(
+                0.,
+                0.,
+                i as f64 * 0.5,
+            ));
+            w.add_object(glass);
+        }
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        assert_eq!(w.intersect(r).0.len(), 200);
+
+        w.max_intersections = 16;
+        let Intersections(xs) = w.intersect(r);
+        assert_eq!(xs.len(), 16);
+        assert_eq!(xs[0].t, 4.);
+        assert!(w.color_at(r, 5).is_finite());
+
+        // Starting inside the stack, the intersections behind the origin
+        // don't use up the cap and the hit is kept.
+        let r = Ray::new(Point(0., 0., 30.2), Vector(0., 0., 1.));
+        let all = World {
+            max_intersections: usize::MAX,
+            ..w.clone()
+        };
+        let uncapped = all.intersect(r);
+        let capped = w.intersect(r);
+        assert!(uncapped.0.iter().filter(|x| x.t < 0.).count() > 16);
+        assert_eq!(capped.0.iter().filter(|x| x.t >= 0.).count(), 16);
+        assert_eq!(capped.hit(), uncapped.hit());
+    }
+    #[test]
+    fn hits_beyond_the_max_distance_are_treated_as_misses() {
+        let mut w = World::empty();
+        w.add_light(PointLight::new(Point(0., 10., 0.), Color::white()));
+        w.add_object(Object::plane().set_transform(Transform::translation(0., -1., 0.)));
+        w.max_distance = 50.;
+        let near = Ray::new(Point(0., 0., 0.), Vector(0., -1., 1.).normalize());
+        assert_ne!(w.color_at(near, 5), Color::black());
+        let far = Ray::new(Point(0., 0., 0.), Vector(0., -1., 100.).normalize());
+        assert_eq!(w.color_at(far, 5), Color::black());
+        w.max_distance = f64::INFINITY;
+        assert_ne!(w.color_at(far, 5), Color::black());
+    }
+    #[test]
+    fn a_larger_shadow_bias_removes_acne_in_large_scenes() {
+        let scale = 1e12;
+        let eye = Point(0., 0., -5. * scale);
+        let mut w = World::empty();
+        w.add_light(PointLight::new(eye, Color::white()));
+        w.add_object(Object::sphere().set_transform(Transform::scaling(scale, scale, scale)));
+        // Every visible point faces the light, so any pixel left with only
+        // the ambient term has shadowed itself.
+        let count_acne = |w: &World| {
+            let mut acne = 0;
+            for i in -10..10 {
+                for j in -10..10 {
+                    let target = Point(i as f64 * 0.05 * scale, j as f64 * 0.05 * scale, 0.);
+                    let r = Ray::new(eye, (target - eye).normalize());
+                    if w.color_at(r, 5).almost_eq(Color(0.1, 0.1, 0.1), EPSILON) {
+                        acne += 1;
+                    }
+                }
+            }
+            acne
+        };
+        assert_eq!(w.shadow_bias, EPSILON);
+        assert!(count_acne(&w) > 0);
+        w.shadow_bias = 1e-9 * scale;
+        assert_eq!(count_acne(&w), 0);
+    }
+    #[test]
+    fn a_shadow_catcher_only_shows_the_shadows_falling_on_it() {
+        let mut w = World::empty();
+        w.add_light(PointLight::new(Point(0., 10., 0.), Color::white()));
+        w.add_object(Object::plane().set_shadow_catcher(0.8));
+        w.add_object(Object::sphere().set_transform(Transform::translation(0., 2., 0.)));
+        let backdrop = Object::plane()
+            .set_ambient(1.)
+            .set_diffuse(0.)
+            .set_specular(0.)
+            .set_transform(Transform::translation(0., -10., 0.));
+        w.add_object(backdrop);
+
+        let eye = Point(5., 5., 0.);
+        let unshadowed = Ray::new(eye, (Point(3., 0., 0.) - eye).normalize());
+        assert_almost_eq!(w.color_at(unshadowed, 5), Color::white());
+        let shadowed = Ray::new(eye, (Point(0., 0., 0.) - eye).normalize());
+        assert_almost_eq!(w.color_at(shadowed, 5), Color(0.2, 0.2, 0.2));
+    }
+    #[test]
+    fn colors_for_a_batch_of_rays_match_individual_calls() {
+        let w = World::default();
+        let origin = Point(0., 0., -5.);
+        let rays: Vec<Ray> = [
+            Point(0., 0., 0.),
+            Point(-0.8, 0.8, 0.),
+            Point(0.8, 0.8, 0.),
+            Point(-0.8, -0.8, 0.),
+            Point(0.8, -0.8, 0.),
+        ]
+        .into_iter()
+        .map(|p| Ray::new(origin, (p - origin).normalize()))
+        .collect();
+        let colors = w.colors_for_rays(&rays, 5);
+        assert_eq!(colors.len(), rays.len());
+        for (r, c) in rays.iter().zip(colors) {
+            assert_eq!(c, w.color_at(*r, 5));
+        }
+    }
+    #[test]
+    fn tracing_the_path_of_a_ray_between_two_mirrors() {
+        let mut w = World::empty();
+        w.add_light(PointLight::new(Point(0., 0., -10.), Color::white()));
+        w.add_object(
+            Object::plane()
+                .set_reflective(0.5)
+                .set_transform(Transform::translation(0., -1., 0.)),
+        );
+        w.add_object(
+            Object::plane()
+                .set_reflective(0.5)
+                .set_transform(Transform::translation(0., 1., 0.)),
+        );
+        let r = Ray::new(Point(0., 0., 0.), Vector(0., 1., 1.).normalize());
+        let path = w.trace_path(r, 3);
+        assert_eq!(path.len(), 4);
+        assert_almost_eq!(path[0].0, 2f64.sqrt());
+        for (t, _) in &path[1..] {
+            assert_almost_eq!(*t, 2. * 2f64.sqrt(), 1e-3);
+        }
+        for pair in path.windows(2) {
+            assert!(pair[1].1 .0 < pair[0].1 .0);
+        }
+        let total = path.iter().fold(Color::black(), |acc, (_, c)| acc + *c);
+        assert_almost_eq!(total, w.color_at(r, 3));
+    }
+    #[test]
+    fn tracing_the_path_of_a_ray_stops_at_a_matte_surface() {
+        let w = World::default();
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        let path = w.trace_path(r, 5);
+        assert_eq!(path.len(), 1);
+        assert_eq!(path[0], (4., w.color_at(r, 5)));
+    }
+    #[test]
+    fn tracing_the_path_of_a_ray_stops_at_a_rough_mirror() {
+        let mut w = World::default();
+        w.add_object(
+            Object::plane()
+                .set_reflective(0.5)
+                .set_roughness(0.2)
+                .set_transform(Transform::translation(0., -1., 0.)),
+        );
+        let r = Ray::new(Point(0., 0., -3.), Vector(0., -1., 1.).normalize());
+        let path = w.trace_path(r, 5);
+        assert_eq!(path.len(), 1);
+        assert_almost_eq!(path[0].0, 2f64.sqrt());
+    }
+    #[test]
+    fn tracing_the_path_of_a_ray_skips_objects_hidden_from_the_camera() {
+        let mut w = World::default();
+        w.objects[0].set_visible_primary(false);
+        w.objects[1].set_visible_primary(false);
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        assert!(w.trace_path(r, 5).is_empty());
+    }
+    #[test]
     fn color_at_with_mutually_reflective_surfaces() {
         let mut w = World::empty();
 
-        w.add_light(PointLight {
-            position: Point(0., 0., 0.),
-            intensity: Color::white(),
-        });
+        w.add_light(PointLight::new(Point(0., 0., 0.), Color::white()));
         let lower = Object::plane()
             .set_reflective(1.)
             .set_transform(Transform::translation(0., -1., 0.));
@@ -346,12 +1627,36 @@ mod tests {
         assert!(true);
     }
     #[test]
+    fn weak_reflections_stop_recursing_before_the_depth_limit() {
+        // Rays traced bouncing between two facing mirrors.
+        let rays = |reflective: f64, min_throughput: f64| {
+            let mut w = World::empty();
+            w.add_light(PointLight::new(Point(0., 0., -5.), Color::white()));
+            w.add_object(
+                Object::plane_at(Point(0., -1., 0.), Vector(0., 1., 0.)).set_reflective(reflective),
+            );
+            w.add_object(
+                Object::plane_at(Point(0., 1., 0.), Vector(0., -1., 0.)).set_reflective(reflective),
+            );
+            w.min_throughput = min_throughput;
+            let counter = RayCounter::default();
+            w.color_at_counted(
+                Ray::new(Point(0., 0., 0.), Vector(0., 1., 0.)),
+                10,
+                &counter,
+            );
+            counter.total_rays()
+        };
+        assert!(rays(0.1, 1e-3) < rays(0.1, 0.));
+        assert_eq!(rays(0.9, 1e-3), rays(0.9, 0.));
+    }
+    #[test]
     fn the_reflected_color_at_the_maximum_recursive_depth() {
         let mut w = World::default();
         let shape = Object::plane()
             .set_reflective(0.5)
             .set_transform(Transform::translation(0., -1., 0.));
-        w.add_object(shape);
+        w.add_object(shape.clone());
         let r = Ray {
             origin: Point(0., 0., -3.),
             direction: Vector(0., -2f64.sqrt() / 2., 2f64.sqrt() / 2.),
@@ -366,7 +1671,7 @@ mod tests {
     #[test]
     fn the_refracted_color_with_an_opaque_surface() {
         let w = World::default();
-        let shape = w.objects[0];
+        let shape = &w.objects[0];
         let r = Ray {
             origin: Point(0., 0., -5.),
             direction: Vector(0., 0., 1.),
@@ -387,7 +1692,7 @@ mod tests {
     #[test]
     fn the_refracted_color_at_the_maximum_recursive_depth() {
         let w = World::default();
-        let mut shape = w.objects[0];
+        let mut shape = w.objects[0].clone();
         shape.set_transparency(1.0).set_refractive_index(1.5);
         let r = Ray {
             origin: Point(0., 0., -5.),
@@ -410,6 +1715,10 @@ mod tests {
     fn the_refracted_color_under_total_internal_reflection() {
         let mut w = World::default();
         let shape = w.objects[0].set_transparency(1.).set_refractive_index(1.5);
+        // Large enough for the internally reflected ray to hit it.
+        w.objects[1]
+            .set_transform(Transform::scaling(0.8, 0.8, 0.8))
+            .set_ambient(1.);
         let r = Ray {
             origin: Point(0., 0., 2f64.sqrt() / 2.),
             direction: Vector(0., 1., 0.),
@@ -425,7 +1734,10 @@ mod tests {
             },
         ]);
         let comps = xs[1].prepare_computations(r, 1, &xs);
-        assert_eq!(w.refracted_color(&comps, 5), Color::black());
+        assert_eq!(comps.refracted_ray(), None);
+        let mirrored = w.secondary_color_at(comps.reflected_ray(), 4, 1., &RayCounter::default());
+        assert_ne!(mirrored, Color::black());
+        assert_eq!(w.refracted_color(&comps, 5), mirrored);
     }
     #[test]
     fn the_refracted_color_with_a_refracted_ray() {
@@ -434,10 +1746,10 @@ mod tests {
         let a = w.objects[0]
             .set_pattern(Pattern::test_pattern())
             .set_ambient(1.0);
-        w.objects[0] = a;
+        w.objects[0] = a.clone();
 
         let b = w.objects[1].set_transparency(1.).set_refractive_index(1.5);
-        w.objects[1] = b;
+        w.objects[1] = b.clone();
         let r = Ray {
             origin: Point(0., 0., 0.1),
             direction: Vector(0., 1., 0.),
@@ -470,7 +1782,7 @@ mod tests {
             .set_transform(Transform::translation(0., -1., 0.))
             .set_transparency(0.5)
             .set_refractive_index(1.5);
-        w.add_object(floor);
+        w.add_object(floor.clone());
         let ball = Object::sphere()
             .set_color(Color(1., 0., 0.))
             .set_ambient(0.5)
@@ -487,4 +1799,25 @@ mod tests {
         let comps = xs[0].prepare_computations(r, 0, &xs);
         assert_almost_eq!(w.shade_hit(&comps, 5), Color(0.93642, 0.68642, 0.68642));
     }
+
+    #[test]
+    fn energy_conservation_dims_materials_reflecting_more_than_they_receive() {
+        let mut w = World::default();
+        w.objects[0].set_reflective(0.9).set_diffuse(0.9);
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        let without = w.color_at(r, 5);
+        w.energy_conservation = true;
+        let with = w.color_at(r, 5);
+        assert!(with.0 < without.0 && with.1 < without.1 && with.2 < without.2);
+    }
+
+    #[test]
+    fn energy_conservation_leaves_plausible_materials_unchanged() {
+        let mut w = World::default();
+        w.objects[0].set_reflective(0.2);
+        let r = Ray::new(Point(0., 0., -5.), Vector(0., 0., 1.));
+        let without = w.color_at(r, 5);
+        w.energy_conservation = true;
+        assert_eq!(w.color_at(r, 5), without);
+    }
 }